@@ -12,7 +12,7 @@ pub fn query_recipes_for_build<'a>(
         .map(|rid| recipes.get(*rid))
 }
 
-/// Convenience: pick the first available recipe (simple default policy).
-pub fn first_recipe_for<'a>(product_id: &str, recipes: &'a RecipeDb) -> Option<&'a Recipe> {
-    query_recipes_for_build(product_id, recipes).next()
+/// Look a recipe up by its id, e.g. one named by a production plan step.
+pub fn recipe_by_id<'a>(recipe_id: &str, recipes: &'a RecipeDb) -> Option<&'a Recipe> {
+    recipes.0.iter().map(|(_, r)| r).find(|r| r.id == recipe_id)
 }
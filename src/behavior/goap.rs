@@ -0,0 +1,318 @@
+use crate::behavior_trees::BehaviorTreeDb;
+use crate::btree::BehaviorStatus::{Failure, Running, Success};
+use crate::btree::{BehaviorContext, BehaviorStatus, BehaviorTreeNode, Sequence};
+use crate::entity_commands::EntityCommand;
+use crate::Knowledge;
+use hecs::World as ComponentRegistry;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+
+// Upper bound on expanded states per search; action sets are small, this only
+// stops a bad action set from stalling a tick.
+const MAX_EXPANSIONS: usize = 4096;
+
+/// Abstract world state: named boolean facts. Facts that are not set count as false.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WorldState(BTreeMap<String, bool>);
+
+impl WorldState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with(mut self, fact: &str, value: bool) -> Self {
+        self.set(fact, value);
+        self
+    }
+    pub fn set(&mut self, fact: &str, value: bool) {
+        self.0.insert(String::from(fact), value);
+    }
+    pub fn get(&self, fact: &str) -> bool {
+        self.0.get(fact).copied().unwrap_or(false)
+    }
+    /// Every fact in `conditions` has the same value here.
+    pub fn satisfies(&self, conditions: &WorldState) -> bool {
+        conditions
+            .0
+            .iter()
+            .all(|(fact, value)| self.get(fact) == *value)
+    }
+    fn apply(&self, effects: &WorldState) -> WorldState {
+        let mut next = self.clone();
+        for (fact, value) in &effects.0 {
+            next.set(fact, *value);
+        }
+        next
+    }
+}
+
+/// Something an agent can do, described by what it needs and what it changes.
+/// `tree` names the behavior tree asset that carries the action out.
+pub struct GoapAction {
+    pub name: String,
+    pub cost: u32,
+    pub preconditions: WorldState,
+    pub effects: WorldState,
+    pub tree: String,
+}
+
+impl GoapAction {
+    pub fn new(name: &str, cost: u32, tree: &str) -> Self {
+        Self {
+            name: String::from(name),
+            cost,
+            preconditions: WorldState::new(),
+            effects: WorldState::new(),
+            tree: String::from(tree),
+        }
+    }
+    pub fn requires(mut self, fact: &str, value: bool) -> Self {
+        self.preconditions.set(fact, value);
+        self
+    }
+    pub fn causes(mut self, fact: &str, value: bool) -> Self {
+        self.effects.set(fact, value);
+        self
+    }
+}
+
+/// Cheapest sequence of actions (indices into `actions`) that takes `start` to
+/// a state satisfying `goal`. Uniform-cost search; ties go to the action listed
+/// first, so the same inputs always give the same plan.
+pub fn plan(start: &WorldState, goal: &WorldState, actions: &[GoapAction]) -> Option<Vec<usize>> {
+    let mut states = vec![(start.clone(), None::<(usize, usize)>)]; // (state, (parent, action))
+    let mut closed = BTreeSet::new();
+    let mut open = BinaryHeap::new();
+    open.push(Reverse((0u32, 0usize)));
+
+    while let Some(Reverse((cost, node))) = open.pop() {
+        let state = states[node].0.clone();
+        if state.satisfies(goal) {
+            let mut steps = Vec::new();
+            let mut cur = node;
+            while let Some((parent, action)) = states[cur].1 {
+                steps.push(action);
+                cur = parent;
+            }
+            steps.reverse();
+            return Some(steps);
+        }
+        if !closed.insert(state.clone()) {
+            continue;
+        }
+        if closed.len() > MAX_EXPANSIONS {
+            return None;
+        }
+
+        for (i, action) in actions.iter().enumerate() {
+            if !state.satisfies(&action.preconditions) {
+                continue;
+            }
+            let next = state.apply(&action.effects);
+            if closed.contains(&next) {
+                continue;
+            }
+            states.push((next, Some((node, i))));
+            open.push(Reverse((cost + action.cost, states.len() - 1)));
+        }
+    }
+    None
+}
+
+/// Turn a plan into a sequence running each action's tree in order, or `None`
+/// if one of the trees doesn't exist.
+pub fn compile(
+    name: &str,
+    plan: &[usize],
+    actions: &[GoapAction],
+    trees: &BehaviorTreeDb,
+) -> Option<Box<dyn BehaviorTreeNode>> {
+    let steps = plan
+        .iter()
+        .map(|i| trees.build(&actions[*i].tree))
+        .collect::<Option<Vec<_>>>()?;
+    Some(Sequence::of(name, steps))
+}
+
+pub type Sensor = fn(&Knowledge, &ComponentRegistry, &BehaviorContext) -> WorldState;
+
+/// Behavior node that reaches `goal` by planning over `actions` instead of a
+/// hand-built tree. The world is sensed and a plan compiled when the node
+/// starts; if the plan fails the node fails, and the next run replans.
+pub struct GoapGoal {
+    name: String,
+    goal: WorldState,
+    actions: Vec<GoapAction>,
+    sense: Sensor,
+    current: Option<Box<dyn BehaviorTreeNode>>,
+}
+
+impl GoapGoal {
+    pub fn new(name: &str, goal: WorldState, actions: Vec<GoapAction>, sense: Sensor) -> Box<Self> {
+        Box::new(Self {
+            name: String::from(name),
+            goal,
+            actions,
+            sense,
+            current: None,
+        })
+    }
+}
+
+impl BehaviorTreeNode for GoapGoal {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        if self.current.is_none() {
+            let state = (self.sense)(knowledge, registry, ctx);
+            if state.satisfies(&self.goal) {
+                return Success;
+            }
+            let compiled = plan(&state, &self.goal, &self.actions)
+                .and_then(|steps| compile(&self.name, &steps, &self.actions, ctx.trees));
+            let Some(compiled) = compiled else {
+                return Failure;
+            };
+            self.current = Some(compiled);
+        }
+
+        let status = self
+            .current
+            .as_mut()
+            .unwrap()
+            .tick(knowledge, entity_commands, registry, ctx);
+        match status {
+            Running => Running,
+            Success => {
+                self.current = None;
+                Success
+            }
+            Failure => {
+                self.current = None;
+                Failure
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.current = None;
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("GoapGoal({})", self.name))
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        if let Some(current) = self.current.as_mut() {
+            current.on_abort(knowledge, registry, ctx);
+        }
+        self.current = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behaviors::agent_actions;
+
+    fn names(steps: &[usize], actions: &[GoapAction]) -> Vec<String> {
+        steps.iter().map(|i| actions[*i].name.clone()).collect()
+    }
+
+    #[test]
+    fn cheapest_plan_wins_over_fewer_steps() {
+        let actions = vec![
+            GoapAction::new("shortcut", 10, "t").causes("done", true),
+            GoapAction::new("prepare", 1, "t").causes("ready", true),
+            GoapAction::new("finish", 2, "t")
+                .requires("ready", true)
+                .causes("done", true),
+        ];
+        let goal = WorldState::new().with("done", true);
+        let steps = plan(&WorldState::new(), &goal, &actions).unwrap();
+        assert_eq!(names(&steps, &actions), ["prepare", "finish"]);
+
+        // already there: nothing to do
+        let done = WorldState::new().with("done", true);
+        assert_eq!(plan(&done, &goal, &actions), Some(Vec::new()));
+    }
+
+    #[test]
+    fn equal_costs_go_to_the_action_listed_first() {
+        let actions = vec![
+            GoapAction::new("left", 1, "t").causes("done", true),
+            GoapAction::new("right", 1, "t").causes("done", true),
+        ];
+        let goal = WorldState::new().with("done", true);
+        assert_eq!(plan(&WorldState::new(), &goal, &actions), Some(vec![0]));
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_plan() {
+        let actions = vec![
+            GoapAction::new("open", 1, "t")
+                .requires("has_key", true)
+                .causes("open", true),
+            GoapAction::new("wander", 1, "t").causes("tired", true),
+        ];
+        let goal = WorldState::new().with("open", true);
+        assert_eq!(plan(&WorldState::new(), &goal, &actions), None);
+    }
+
+    #[test]
+    fn compiled_plan_runs_the_action_trees_in_order() {
+        let trees = BehaviorTreeDb::load_from_assets(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/behaviors"
+        ))
+        .unwrap();
+        let actions = agent_actions();
+        for action in &actions {
+            assert!(trees.contains(&action.tree), "{}", action.tree);
+        }
+        let goal = WorldState::new().with("has_house", true);
+        let steps = plan(&WorldState::new(), &goal, &actions).unwrap();
+        assert_eq!(
+            names(&steps, &actions),
+            [
+                "craft_tools",
+                "choose_house_recipe",
+                "reserve_site",
+                "collect_materials",
+                "move_to_site",
+                "build"
+            ]
+        );
+
+        let tree = compile("have_house", &steps, &actions, &trees).unwrap();
+        assert_eq!(tree.name(), "Sequence(have_house)");
+        let children: Vec<String> = tree
+            .children()
+            .iter()
+            .map(|c| c.name().into_owned())
+            .collect();
+        assert_eq!(
+            children,
+            [
+                "DoUntil",
+                "ChooseRecipe(house_basic)",
+                "ReserveBuildingSite",
+                "DoUntil",
+                "MoveToSite",
+                "BuildOnSite"
+            ]
+        );
+
+        let missing = vec![GoapAction::new("ghost", 1, "no_such_tree")];
+        assert!(compile("ghost", &[0], &missing, &trees).is_none());
+    }
+}
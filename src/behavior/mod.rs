@@ -1 +1,3 @@
 pub mod build_knowledge;
+pub mod goap;
+pub mod production;
@@ -0,0 +1,542 @@
+use super::build_knowledge::query_recipes_for_build;
+use crate::items::ItemDb;
+use crate::recipes::types::{ProductKind, Recipe};
+use crate::recipes::RecipeDb;
+use hecs::Entity;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// One thing the agent has to do, in the order it has to be done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanStep {
+    /// Pick up `qty` items lying around the map.
+    Gather { item_id: String, qty: u32 },
+    /// Run `recipe_id` `times` times.
+    Craft {
+        recipe_id: String,
+        product_id: String,
+        times: u32,
+    },
+    Build {
+        recipe_id: String,
+        product_id: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProductionPlan {
+    pub steps: Vec<PlanStep>,
+}
+
+impl ProductionPlan {
+    /// Recipe of the first tool or intermediate that has to be crafted on
+    /// the way, not counting the product's own recipe.
+    pub fn next_craft(&self) -> Option<&str> {
+        let (_, before) = self.steps.split_last()?;
+        before.iter().find_map(|step| match step {
+            PlanStep::Craft { recipe_id, .. } => Some(recipe_id.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Recipe of the last step, the one that makes the planned product.
+    /// `None` if the product is held already or only gathered.
+    pub fn final_recipe(&self) -> Option<&str> {
+        match self.steps.last()? {
+            PlanStep::Craft { recipe_id, .. } | PlanStep::Build { recipe_id, .. } => {
+                Some(recipe_id)
+            }
+            PlanStep::Gather { .. } => None,
+        }
+    }
+}
+
+/// Why a product can't be made. `Blocked` wraps the reason of the first
+/// recipe that was tried, so the message reads down the dependency chain.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PlanError {
+    #[error("{product:?}: no recipe makes it and it can't be gathered")]
+    NoSource { product: String },
+    #[error("{product:?}: no item can serve as tool {tool:?}")]
+    NoTool { product: String, tool: String },
+    #[error("{product:?}: recipe cycle {}", .chain.join(" -> "))]
+    Cycle { product: String, chain: Vec<String> },
+    #[error("{product:?} via recipe {recipe:?}: {reason}")]
+    Blocked {
+        product: String,
+        recipe: String,
+        reason: Box<PlanError>,
+    },
+}
+
+/// Plan how to get one `product_id` starting from `inventory`.
+/// Inventory items are used first, surplus from crafting is reused by later
+/// steps, and tools are made once and kept. Recipes are tried in index order.
+pub fn plan_production(
+    product_id: &str,
+    inventory: &HashMap<String, Vec<Entity>>,
+    recipes: &RecipeDb,
+    items: &ItemDb,
+) -> Result<ProductionPlan, PlanError> {
+    let mut planner = Planner {
+        recipes,
+        items,
+        state: PlanState {
+            stock: inventory
+                .iter()
+                .map(|(id, entities)| (id.clone(), entities.len() as u32))
+                .collect(),
+            tools: Vec::new(),
+            steps: Vec::new(),
+        },
+        chain: Vec::new(),
+    };
+    planner.resolve(product_id, 1)?;
+    Ok(ProductionPlan {
+        steps: planner.state.steps,
+    })
+}
+
+#[derive(Clone)]
+struct PlanState {
+    stock: HashMap<String, u32>,
+    tools: Vec<String>,
+    steps: Vec<PlanStep>,
+}
+
+struct Planner<'a> {
+    recipes: &'a RecipeDb,
+    items: &'a ItemDb,
+    state: PlanState,
+    chain: Vec<String>,
+}
+
+impl Planner<'_> {
+    fn resolve(&mut self, product_id: &str, qty: u32) -> Result<(), PlanError> {
+        let in_stock = self.state.stock.get(product_id).copied().unwrap_or(0);
+        let taken = in_stock.min(qty);
+        if taken > 0 {
+            self.state
+                .stock
+                .insert(product_id.to_string(), in_stock - taken);
+        }
+        let missing = qty - taken;
+        if missing == 0 {
+            return Ok(());
+        }
+
+        if self.chain.iter().any(|p| p == product_id) {
+            let mut chain = self.chain.clone();
+            chain.push(product_id.to_string());
+            return Err(PlanError::Cycle {
+                product: product_id.to_string(),
+                chain,
+            });
+        }
+
+        let mut first_error = None;
+        for recipe in query_recipes_for_build(product_id, self.recipes) {
+            let snapshot = self.state.clone();
+            self.chain.push(product_id.to_string());
+            let result = self.apply_recipe(recipe, missing);
+            self.chain.pop();
+            match result {
+                Ok(()) => return Ok(()),
+                Err(reason) => {
+                    self.state = snapshot;
+                    first_error.get_or_insert(PlanError::Blocked {
+                        product: product_id.to_string(),
+                        recipe: recipe.id.clone(),
+                        reason: Box::new(reason),
+                    });
+                }
+            }
+        }
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+
+        // nothing makes it, so it has to be lying around somewhere
+        let gatherable = self
+            .items
+            .def_id(product_id)
+            .is_some_and(|id| self.items.get(id).spawn.is_some());
+        if !gatherable {
+            return Err(PlanError::NoSource {
+                product: product_id.to_string(),
+            });
+        }
+        let existing = self.state.steps.iter_mut().find_map(|s| match s {
+            PlanStep::Gather { item_id, qty } if item_id == product_id => Some(qty),
+            _ => None,
+        });
+        match existing {
+            Some(qty) => *qty += missing,
+            None => self.state.steps.push(PlanStep::Gather {
+                item_id: product_id.to_string(),
+                qty: missing,
+            }),
+        }
+        Ok(())
+    }
+
+    fn apply_recipe(&mut self, recipe: &Recipe, qty: u32) -> Result<(), PlanError> {
+        let times = qty.div_ceil(recipe.product.qty.max(1));
+
+        for tool in &recipe.tools {
+            self.resolve_tool(&recipe.product.id, tool)?;
+        }
+        for ingredient in &recipe.ingredients {
+            self.resolve(&ingredient.id, ingredient.qty * times)?;
+        }
+
+        let product_id = recipe.product.id.clone();
+        let surplus = recipe.product.qty * times - qty;
+        if surplus > 0 {
+            *self.state.stock.entry(product_id.clone()).or_default() += surplus;
+        }
+        self.state.steps.push(match recipe.product.kind {
+            ProductKind::Item => PlanStep::Craft {
+                recipe_id: recipe.id.clone(),
+                product_id,
+                times,
+            },
+            ProductKind::Building => PlanStep::Build {
+                recipe_id: recipe.id.clone(),
+                product_id,
+            },
+        });
+        Ok(())
+    }
+
+    /// A tool is an item id or an item tag. Held or already planned tools are
+    /// reused, otherwise the first matching item that can be made is planned.
+    fn resolve_tool(&mut self, product_id: &str, tool: &str) -> Result<(), PlanError> {
+        let held = self
+            .state
+            .stock
+            .iter()
+            .any(|(id, n)| *n > 0 && self.is_tool(id, tool));
+        if held || self.state.tools.iter().any(|id| self.is_tool(id, tool)) {
+            return Ok(());
+        }
+
+        let mut candidates: Vec<String> = self
+            .items
+            .0
+            .iter()
+            .filter(|(_, def)| def.id == tool || def.has_tag(tool))
+            .map(|(_, def)| def.id.clone())
+            .collect();
+        if candidates.is_empty() && !self.recipes.recipes_for(tool).is_empty() {
+            candidates.push(tool.to_string());
+        }
+
+        let mut first_error = None;
+        for candidate in candidates {
+            let snapshot = self.state.clone();
+            match self.resolve(&candidate, 1) {
+                Ok(()) => {
+                    self.state.tools.push(candidate);
+                    return Ok(());
+                }
+                Err(e) => {
+                    self.state = snapshot;
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or_else(|| PlanError::NoTool {
+            product: product_id.to_string(),
+            tool: tool.to_string(),
+        }))
+    }
+
+    fn is_tool(&self, item_id: &str, tool: &str) -> bool {
+        item_id == tool
+            || self
+                .items
+                .def_id(item_id)
+                .is_some_and(|id| self.items.get(id).has_tag(tool))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::types::{ItemDef, ItemIndex, SpawnRule};
+    use crate::map::TerrainKind;
+    use crate::recipes::types::{Ingredient, Product, RecipeIndex};
+
+    fn item(id: &str, tags: &[&str], gatherable: bool) -> ItemDef {
+        ItemDef {
+            id: String::from(id),
+            name: String::from(id),
+            color: (0, 0, 0, 255),
+            shape: (0.2, 0.2),
+            stack_size: 1,
+            tags: tags.iter().map(|t| String::from(*t)).collect(),
+            spawn: gatherable.then(|| SpawnRule {
+                count: 1,
+                terrain: vec![TerrainKind::Grass],
+            }),
+        }
+    }
+
+    fn recipe(
+        id: &str,
+        kind: ProductKind,
+        product: (&str, u32),
+        ingredients: &[(&str, u32)],
+        tools: &[&str],
+    ) -> Recipe {
+        Recipe {
+            id: String::from(id),
+            product: Product {
+                kind,
+                id: String::from(product.0),
+                qty: product.1,
+            },
+            ingredients: ingredients
+                .iter()
+                .map(|(id, qty)| Ingredient {
+                    kind: ProductKind::Item,
+                    id: String::from(*id),
+                    qty: *qty,
+                })
+                .collect(),
+            tools: tools.iter().map(|t| String::from(*t)).collect(),
+            time_ms: 1000,
+            tags: Vec::new(),
+            flags: 0,
+        }
+    }
+
+    fn dbs(items: Vec<ItemDef>, recipes: Vec<Recipe>) -> (ItemDb, RecipeDb) {
+        let mut item_index = ItemIndex::default();
+        for def in items {
+            item_index.insert(def);
+        }
+        let mut recipe_index = RecipeIndex::default();
+        for r in recipes {
+            recipe_index.insert(r);
+        }
+        (ItemDb(item_index), RecipeDb(recipe_index))
+    }
+
+    // the starter items and recipes from the assets
+    fn starter() -> (ItemDb, RecipeDb) {
+        dbs(
+            vec![
+                item("wood_log", &["wood", "raw"], true),
+                item("stone_block", &["stone", "raw"], true),
+                item("wood_plank", &["wood"], false),
+                item("stone_chunk", &["stone"], false),
+                item("hammer_wood", &["tool", "hammer"], false),
+            ],
+            vec![
+                recipe(
+                    "house/basic_v1",
+                    ProductKind::Building,
+                    ("house_basic", 1),
+                    &[("wood_log", 12), ("stone_block", 8)],
+                    &["hammer"],
+                ),
+                recipe(
+                    "tool/hammer_wood_v1",
+                    ProductKind::Item,
+                    ("hammer_wood", 1),
+                    &[("wood_plank", 2), ("stone_chunk", 1)],
+                    &[],
+                ),
+                recipe(
+                    "wood/plank_v1",
+                    ProductKind::Item,
+                    ("wood_plank", 2),
+                    &[("wood_log", 1)],
+                    &[],
+                ),
+                recipe(
+                    "stone/chunk_v1",
+                    ProductKind::Item,
+                    ("stone_chunk", 2),
+                    &[("stone_block", 1)],
+                    &[],
+                ),
+            ],
+        )
+    }
+
+    fn gather(item_id: &str, qty: u32) -> PlanStep {
+        PlanStep::Gather {
+            item_id: String::from(item_id),
+            qty,
+        }
+    }
+
+    fn craft(recipe_id: &str, product_id: &str, times: u32) -> PlanStep {
+        PlanStep::Craft {
+            recipe_id: String::from(recipe_id),
+            product_id: String::from(product_id),
+            times,
+        }
+    }
+
+    fn holding(items: &[(&str, usize)]) -> HashMap<String, Vec<Entity>> {
+        items
+            .iter()
+            .map(|(id, n)| (String::from(*id), vec![Entity::DANGLING; *n]))
+            .collect()
+    }
+
+    #[test]
+    fn hammer_is_made_before_the_house() {
+        let (items, recipes) = starter();
+        let plan = plan_production("house_basic", &HashMap::new(), &recipes, &items).unwrap();
+        assert_eq!(
+            plan.steps,
+            vec![
+                gather("wood_log", 13),
+                craft("wood/plank_v1", "wood_plank", 1),
+                gather("stone_block", 9),
+                craft("stone/chunk_v1", "stone_chunk", 1),
+                craft("tool/hammer_wood_v1", "hammer_wood", 1),
+                PlanStep::Build {
+                    recipe_id: String::from("house/basic_v1"),
+                    product_id: String::from("house_basic"),
+                },
+            ]
+        );
+        assert_eq!(plan.next_craft(), Some("wood/plank_v1"));
+        assert_eq!(plan.final_recipe(), Some("house/basic_v1"));
+    }
+
+    #[test]
+    fn held_items_and_tools_are_used_first() {
+        let (items, recipes) = starter();
+        let inventory = holding(&[("hammer_wood", 1), ("wood_log", 5)]);
+        let plan = plan_production("house_basic", &inventory, &recipes, &items).unwrap();
+        assert_eq!(plan.next_craft(), None);
+        assert_eq!(
+            plan.steps[..2],
+            [gather("wood_log", 7), gather("stone_block", 8)]
+        );
+
+        let plan = plan_production("wood_log", &inventory, &recipes, &items).unwrap();
+        assert!(plan.steps.is_empty());
+        assert_eq!(plan.final_recipe(), None);
+    }
+
+    #[test]
+    fn surplus_output_is_reused() {
+        // one log makes two planks: one for the hammer, one for the shed
+        let (items, recipes) = dbs(
+            vec![
+                item("wood_log", &[], true),
+                item("wood_plank", &[], false),
+                item("hammer_wood", &["hammer"], false),
+            ],
+            vec![
+                recipe(
+                    "shed",
+                    ProductKind::Building,
+                    ("shed", 1),
+                    &[("wood_plank", 1)],
+                    &["hammer"],
+                ),
+                recipe(
+                    "hammer",
+                    ProductKind::Item,
+                    ("hammer_wood", 1),
+                    &[("wood_plank", 1)],
+                    &[],
+                ),
+                recipe(
+                    "plank",
+                    ProductKind::Item,
+                    ("wood_plank", 2),
+                    &[("wood_log", 1)],
+                    &[],
+                ),
+            ],
+        );
+        let plan = plan_production("shed", &HashMap::new(), &recipes, &items).unwrap();
+        assert_eq!(
+            plan.steps,
+            vec![
+                gather("wood_log", 1),
+                craft("plank", "wood_plank", 1),
+                craft("hammer", "hammer_wood", 1),
+                PlanStep::Build {
+                    recipe_id: String::from("shed"),
+                    product_id: String::from("shed"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_products_say_why() {
+        let (items, recipes) = dbs(
+            vec![item("gem", &[], false), item("wood_log", &[], true)],
+            vec![
+                recipe("ring", ProductKind::Item, ("ring", 1), &[("gem", 1)], &[]),
+                recipe(
+                    "table",
+                    ProductKind::Item,
+                    ("table", 1),
+                    &[("wood_log", 4)],
+                    &["saw"],
+                ),
+            ],
+        );
+        let no_gem = PlanError::NoSource {
+            product: String::from("gem"),
+        };
+        assert_eq!(
+            plan_production("gem", &HashMap::new(), &recipes, &items),
+            Err(no_gem.clone())
+        );
+        assert_eq!(
+            plan_production("ring", &HashMap::new(), &recipes, &items),
+            Err(PlanError::Blocked {
+                product: String::from("ring"),
+                recipe: String::from("ring"),
+                reason: Box::new(no_gem),
+            })
+        );
+
+        let err = plan_production("table", &HashMap::new(), &recipes, &items).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"table\" via recipe \"table\": \"table\": no item can serve as tool \"saw\""
+        );
+    }
+
+    #[test]
+    fn recipe_cycles_are_reported() {
+        let (items, recipes) = dbs(
+            vec![item("egg", &[], false), item("hen", &[], false)],
+            vec![
+                recipe("lay", ProductKind::Item, ("egg", 1), &[("hen", 1)], &[]),
+                recipe("hatch", ProductKind::Item, ("hen", 1), &[("egg", 1)], &[]),
+            ],
+        );
+        let err = plan_production("egg", &HashMap::new(), &recipes, &items).unwrap_err();
+        let mut reason = &err;
+        while let PlanError::Blocked { reason: inner, .. } = reason {
+            reason = inner;
+        }
+        assert_eq!(
+            *reason,
+            PlanError::Cycle {
+                product: String::from("egg"),
+                chain: vec![
+                    String::from("egg"),
+                    String::from("hen"),
+                    String::from("egg")
+                ],
+            }
+        );
+    }
+}
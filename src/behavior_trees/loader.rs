@@ -0,0 +1,508 @@
+use super::registry::{NodeRegistry, SUBTREE};
+use super::types::{NodeDef, TreeDef};
+use crate::blackboard::{KeyUse, ValueType, EXTERNAL_WRITES};
+use crate::btree::BehaviorTreeNode;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::{fs, path::Path};
+use thiserror::Error;
+
+/// One thing wrong with one tree. `at` is the path of node names from the root.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TreeProblem {
+    #[error("{}: tree {tree:?}: duplicate name, first defined in {}", .file.display(), .first_file.display())]
+    DuplicateName {
+        file: PathBuf,
+        tree: String,
+        first_file: PathBuf,
+    },
+    #[error("{}: tree {tree:?} at {at}: unknown node {node:?}", .file.display())]
+    UnknownNode {
+        file: PathBuf,
+        tree: String,
+        at: String,
+        node: String,
+    },
+    #[error("{}: tree {tree:?} at {at}: {node} takes {expected}, got {found}", .file.display())]
+    BadArity {
+        file: PathBuf,
+        tree: String,
+        at: String,
+        node: String,
+        expected: String,
+        found: String,
+    },
+    #[error("{}: tree {tree:?} at {at}: {node}: {message}", .file.display())]
+    BadArg {
+        file: PathBuf,
+        tree: String,
+        at: String,
+        node: String,
+        message: String,
+    },
+    #[error("{}: tree {tree:?} at {at}: {node} reads blackboard key {key:?} that nothing writes", .file.display())]
+    UnwrittenKey {
+        file: PathBuf,
+        tree: String,
+        at: String,
+        node: String,
+        key: String,
+    },
+    #[error("{}: tree {tree:?} at {at}: {node} reads blackboard key {key:?} as {expected}, but it is written as {found}", .file.display())]
+    KeyType {
+        file: PathBuf,
+        tree: String,
+        at: String,
+        node: String,
+        key: String,
+        expected: String,
+        found: String,
+    },
+}
+
+/// Every problem found in a set of behavior tree files, in file and definition order.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{} problem(s) in behavior tree files:{}", .problems.len(), list(.problems))]
+pub struct TreeValidationError {
+    pub problems: Vec<TreeProblem>,
+}
+
+fn list(problems: &[TreeProblem]) -> String {
+    problems.iter().map(|p| format!("\n  {p}")).collect()
+}
+
+/// Load every `.ron` tree file in `dir` and check that each tree builds with `registry`.
+pub fn load_dir(dir: &Path, registry: &NodeRegistry) -> Result<Vec<TreeDef>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("read_dir {:?}", dir))? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("ron") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut loaded: Vec<(PathBuf, TreeDef)> = Vec::new();
+    for path in paths {
+        let text =
+            fs::read_to_string(&path).with_context(|| format!("read_to_string {:?}", path))?;
+        let list: Vec<TreeDef> =
+            ron::from_str(&text).with_context(|| format!("RON parse {:?}", path))?;
+        loaded.extend(list.into_iter().map(|t| (path.clone(), t)));
+    }
+    Ok(check_trees(loaded, registry)?)
+}
+
+/// Check trees loaded from the given files, in load order, against each other
+/// and `registry`.
+pub fn check_trees(
+    loaded: Vec<(PathBuf, TreeDef)>,
+    registry: &NodeRegistry,
+) -> Result<Vec<TreeDef>, TreeValidationError> {
+    let mut problems = Vec::new();
+    let mut first_seen: HashMap<String, PathBuf> = HashMap::new();
+    let mut trees: HashMap<String, TreeDef> = HashMap::new();
+    let mut order = Vec::new();
+    for (path, tree) in loaded {
+        if let Some(first_file) = first_seen.get(&tree.name) {
+            problems.push(TreeProblem::DuplicateName {
+                file: path,
+                tree: tree.name,
+                first_file: first_file.clone(),
+            });
+            continue;
+        }
+        first_seen.insert(tree.name.clone(), path.clone());
+        order.push((path, tree.name.clone()));
+        trees.insert(tree.name.clone(), tree);
+    }
+
+    // trees may include ones defined later, so check once all are in
+    for (path, name) in &order {
+        let tree = &trees[name];
+        let mut check = Check::new(registry, &trees, path, name, &mut problems);
+        if check.build(&tree.root, &tree.root.node).is_some() {
+            check.check_keys();
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(TreeValidationError { problems });
+    }
+    Ok(order
+        .into_iter()
+        .map(|(_, name)| trees.remove(&name).unwrap())
+        .collect())
+}
+
+/// Builds a node tree, collecting every problem instead of stopping at the first.
+pub(super) struct Check<'a> {
+    pub registry: &'a NodeRegistry,
+    /// Trees `Subtree` nodes can include.
+    pub trees: &'a HashMap<String, TreeDef>,
+    pub file: &'a Path,
+    pub tree: &'a str,
+    pub problems: &'a mut Vec<TreeProblem>,
+    /// Blackboard keys used by the nodes built so far, with node path and name.
+    pub uses: Vec<(String, String, KeyUse)>,
+    // trees being built, outermost first, to catch one including itself
+    including: Vec<&'a str>,
+}
+
+impl<'a> Check<'a> {
+    pub fn new(
+        registry: &'a NodeRegistry,
+        trees: &'a HashMap<String, TreeDef>,
+        file: &'a Path,
+        tree: &'a str,
+        problems: &'a mut Vec<TreeProblem>,
+    ) -> Self {
+        Self {
+            registry,
+            trees,
+            file,
+            tree,
+            problems,
+            uses: Vec::new(),
+            including: vec![tree],
+        }
+    }
+
+    pub fn build(&mut self, def: &NodeDef, at: &str) -> Option<Box<dyn BehaviorTreeNode>> {
+        let mut children: Vec<_> = def
+            .children
+            .iter()
+            .enumerate()
+            .map(|(i, child)| self.build(child, &format!("{at}[{i}].{}", child.node)))
+            .collect();
+
+        let Some(spec) = self.registry.get(&def.node) else {
+            self.problems.push(TreeProblem::UnknownNode {
+                file: self.file.to_path_buf(),
+                tree: self.tree.to_string(),
+                at: at.to_string(),
+                node: def.node.clone(),
+            });
+            return None;
+        };
+        if def.args.len() != spec.args || !spec.children.accepts(def.children.len()) {
+            self.problems.push(TreeProblem::BadArity {
+                file: self.file.to_path_buf(),
+                tree: self.tree.to_string(),
+                at: at.to_string(),
+                node: def.node.clone(),
+                expected: format!("{} arg(s) and {} children", spec.args, spec.children),
+                found: format!(
+                    "{} arg(s) and {} children",
+                    def.args.len(),
+                    def.children.len()
+                ),
+            });
+            return None;
+        }
+
+        if def.node == SUBTREE {
+            children.push(self.subtree(def, at));
+        }
+
+        let children: Option<Vec<_>> = children.into_iter().collect();
+        match (spec.build)(&def.args, children?) {
+            Ok(node) => {
+                for key_use in node.keys() {
+                    self.uses.push((at.to_string(), def.node.clone(), key_use));
+                }
+                Some(node)
+            }
+            Err(message) => {
+                self.problems.push(TreeProblem::BadArg {
+                    file: self.file.to_path_buf(),
+                    tree: self.tree.to_string(),
+                    at: at.to_string(),
+                    node: def.node.clone(),
+                    message,
+                });
+                None
+            }
+        }
+    }
+
+    // The tree a `Subtree` node names, built in place so its keys count as
+    // the including tree's.
+    fn subtree(&mut self, def: &NodeDef, at: &str) -> Option<Box<dyn BehaviorTreeNode>> {
+        let bad_arg = |message: String| TreeProblem::BadArg {
+            file: self.file.to_path_buf(),
+            tree: self.tree.to_string(),
+            at: at.to_string(),
+            node: def.node.clone(),
+            message,
+        };
+        let name = match super::registry::text_arg(&def.args, 0) {
+            Ok(name) => name,
+            Err(message) => {
+                self.problems.push(bad_arg(message));
+                return None;
+            }
+        };
+        let Some((name, tree)) = self.trees.get_key_value(name) else {
+            self.problems
+                .push(bad_arg(format!("unknown tree {name:?}")));
+            return None;
+        };
+        if self.including.contains(&name.as_str()) {
+            self.problems
+                .push(bad_arg(format!("tree {name:?} includes itself")));
+            return None;
+        }
+        self.including.push(name);
+        let root = self.build(&tree.root, &format!("{at}.{}", tree.root.node));
+        self.including.pop();
+        root
+    }
+
+    /// Every key a node of the tree reads has to be written by some node of the
+    /// tree, or from outside, as one of the types the reader accepts. Order
+    /// isn't checked: a key may be written by an earlier run of the tree.
+    pub fn check_keys(&mut self) {
+        let mut written: Vec<(&str, ValueType)> = EXTERNAL_WRITES.to_vec();
+        for (_, _, key_use) in &self.uses {
+            if let KeyUse::Writes(key, ty) = key_use {
+                written.push((key, *ty));
+            }
+        }
+
+        for (at, node, key_use) in &self.uses {
+            let KeyUse::Reads(key, accepted) = key_use else {
+                continue;
+            };
+            let found: Vec<ValueType> = written
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, ty)| *ty)
+                .collect();
+            if found.is_empty() {
+                self.problems.push(TreeProblem::UnwrittenKey {
+                    file: self.file.to_path_buf(),
+                    tree: self.tree.to_string(),
+                    at: at.clone(),
+                    node: node.clone(),
+                    key: key.clone(),
+                });
+            } else if !found.iter().any(|ty| accepted.contains(ty)) {
+                self.problems.push(TreeProblem::KeyType {
+                    file: self.file.to_path_buf(),
+                    tree: self.tree.to_string(),
+                    at: at.clone(),
+                    node: node.clone(),
+                    key: key.clone(),
+                    expected: join_types(accepted),
+                    found: join_types(&found),
+                });
+            }
+        }
+    }
+}
+
+fn join_types(types: &[ValueType]) -> String {
+    let names: Vec<String> = types.iter().map(|ty| ty.to_string()).collect();
+    names.join(" or ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior_trees::BehaviorTreeDb;
+
+    // `files` as (name, RON list of trees), loaded in that order
+    fn check(files: &[(&str, &str)]) -> Vec<TreeProblem> {
+        let mut loaded = Vec::new();
+        for (file, text) in files {
+            let list: Vec<TreeDef> = ron::from_str(text).unwrap();
+            loaded.extend(list.into_iter().map(|t| (PathBuf::from(file), t)));
+        }
+        match check_trees(loaded, &NodeRegistry::with_defaults()) {
+            Ok(_) => Vec::new(),
+            Err(e) => e.problems,
+        }
+    }
+
+    fn one(text: &str) -> Vec<TreeProblem> {
+        check(&[("a.ron", text)])
+    }
+
+    #[test]
+    fn valid_trees_pass() {
+        assert_eq!(
+            one(r#"[
+                (name: "fetch", root: (node: "Sequence", args: ["fetch"], children: [
+                    (node: "FindNearestFood"),
+                    (node: "MoveTo", args: ["target"]),
+                ])),
+                (name: "idle", root: (node: "Timeout", args: [5], children: [(node: "DoNothing")])),
+            ]"#),
+            []
+        );
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let tree = r#"[(name: "idle", root: (node: "DoNothing"))]"#;
+        assert_eq!(
+            check(&[("a.ron", tree), ("b.ron", tree)]),
+            [TreeProblem::DuplicateName {
+                file: PathBuf::from("b.ron"),
+                tree: String::from("idle"),
+                first_file: PathBuf::from("a.ron"),
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_node() {
+        assert_eq!(
+            one(r#"[(name: "t", root: (node: "Inverter", children: [(node: "Dance")]))]"#),
+            [TreeProblem::UnknownNode {
+                file: PathBuf::from("a.ron"),
+                tree: String::from("t"),
+                at: String::from("Inverter[0].Dance"),
+                node: String::from("Dance"),
+            }]
+        );
+    }
+
+    #[test]
+    fn bad_arity() {
+        assert_eq!(
+            one(r#"[(name: "t", root: (node: "Inverter"))]"#),
+            [TreeProblem::BadArity {
+                file: PathBuf::from("a.ron"),
+                tree: String::from("t"),
+                at: String::from("Inverter"),
+                node: String::from("Inverter"),
+                expected: String::from("0 arg(s) and exactly 1 children"),
+                found: String::from("0 arg(s) and 0 children"),
+            }]
+        );
+    }
+
+    #[test]
+    fn bad_arg() {
+        assert_eq!(
+            one(
+                r#"[(name: "t", root: (node: "Timeout", args: ["soon"], children: [(node: "DoNothing")]))]"#
+            ),
+            [TreeProblem::BadArg {
+                file: PathBuf::from("a.ron"),
+                tree: String::from("t"),
+                at: String::from("Timeout"),
+                node: String::from("Timeout"),
+                message: String::from("arg 0 must be an integer, got \"soon\""),
+            }]
+        );
+    }
+
+    #[test]
+    fn unwritten_key() {
+        assert_eq!(
+            one(r#"[(name: "t", root: (node: "MoveTo", args: ["target"]))]"#),
+            [TreeProblem::UnwrittenKey {
+                file: PathBuf::from("a.ron"),
+                tree: String::from("t"),
+                at: String::from("MoveTo"),
+                node: String::from("MoveTo"),
+                key: String::from("target"),
+            }]
+        );
+    }
+
+    #[test]
+    fn key_type() {
+        assert_eq!(
+            one(
+                r#"[(name: "t", root: (node: "Sequence", args: ["t"], children: [
+                (node: "FindItemFromRecipe"),
+                (node: "MoveTo", args: ["target_item"]),
+            ]))]"#
+            ),
+            [TreeProblem::KeyType {
+                file: PathBuf::from("a.ron"),
+                tree: String::from("t"),
+                at: String::from("Sequence[1].MoveTo"),
+                node: String::from("MoveTo"),
+                key: String::from("target_item"),
+                expected: String::from("entity or position"),
+                found: String::from("item id"),
+            }]
+        );
+    }
+
+    #[test]
+    fn subtree_keys_count_for_the_including_tree() {
+        // `find` writes the target `go` moves to, `walk` alone doesn't
+        let problems = one(r#"[
+            (name: "go", root: (node: "Sequence", args: ["go"], children: [
+                (node: "Subtree", args: ["find"]),
+                (node: "MoveTo", args: ["target"]),
+            ])),
+            (name: "find", root: (node: "FindNearestFood")),
+            (name: "walk", root: (node: "MoveTo", args: ["target"])),
+        ]"#);
+        let trees: Vec<&str> = problems
+            .iter()
+            .map(|p| match p {
+                TreeProblem::UnwrittenKey { tree, .. } => tree.as_str(),
+                _ => panic!("unexpected {p}"),
+            })
+            .collect();
+        assert_eq!(trees, ["walk"]);
+    }
+
+    #[test]
+    fn subtree_must_name_another_tree() {
+        let problems = one(r#"[
+            (name: "a", root: (node: "Subtree", args: ["nope"])),
+            (name: "b", root: (node: "Inverter", children: [(node: "Subtree", args: ["c"])])),
+            (name: "c", root: (node: "Subtree", args: ["b"])),
+        ]"#);
+        let messages: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "a.ron: tree \"a\" at Subtree: Subtree: unknown tree \"nope\"",
+                "a.ron: tree \"b\" at Inverter[0].Subtree.Subtree: Subtree: tree \"b\" includes itself",
+                "a.ron: tree \"c\" at Subtree.Inverter[0].Subtree: Subtree: tree \"c\" includes itself",
+            ]
+        );
+    }
+
+    #[test]
+    fn db_builds_trees_with_subtrees() {
+        let dir = std::env::temp_dir().join(format!("anvil-{}-trees", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("trees.ron"),
+            r#"[
+                (name: "eat", root: (node: "Selector", args: ["eat"], children: [
+                    (node: "EatFromInventory"),
+                    (node: "Subtree", args: ["fetch"]),
+                ])),
+                (name: "fetch", root: (node: "Sequence", args: ["fetch"], children: [
+                    (node: "FindNearestFood"),
+                    (node: "MoveTo", args: ["target"]),
+                ])),
+            ]"#,
+        )
+        .unwrap();
+        let db = BehaviorTreeDb::load_from_assets(&dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(db.names(), ["eat", "fetch"]);
+        assert!(db.build("missing").is_none());
+        let eat = db.build("eat").unwrap();
+        assert_eq!(eat.name(), "Selector(eat)");
+        let subtree = eat.children()[1];
+        assert_eq!(subtree.name(), "Subtree(fetch)");
+        assert_eq!(subtree.children()[0].name(), "Sequence(fetch)");
+    }
+}
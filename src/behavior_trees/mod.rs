@@ -0,0 +1,53 @@
+pub mod loader;
+pub mod registry;
+pub mod types;
+
+use crate::btree::BehaviorTreeNode;
+use registry::NodeRegistry;
+use std::collections::HashMap;
+use std::path::Path;
+use types::TreeDef;
+
+/// Behavior trees loaded from assets. Trees are stored as definitions and
+/// built fresh for every agent, since nodes keep per-agent running state.
+pub struct BehaviorTreeDb {
+    registry: NodeRegistry,
+    trees: HashMap<String, TreeDef>,
+}
+
+impl BehaviorTreeDb {
+    pub fn load_from_assets<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let registry = NodeRegistry::with_defaults();
+        let trees = loader::load_dir(dir.as_ref(), &registry)?;
+        Ok(Self {
+            registry,
+            trees: trees.into_iter().map(|t| (t.name.clone(), t)).collect(),
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.trees.contains_key(name)
+    }
+
+    /// Names of all loaded trees, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.trees.keys().map(|n| n.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// A new instance of the tree called `name`, or `None` if there is no such tree.
+    pub fn build(&self, name: &str) -> Option<Box<dyn BehaviorTreeNode>> {
+        let tree = self.trees.get(name)?;
+        let mut problems = Vec::new();
+        let mut check = loader::Check::new(
+            &self.registry,
+            &self.trees,
+            Path::new(""),
+            &tree.name,
+            &mut problems,
+        );
+        // every tree was checked on load, so building it again can't fail
+        check.build(&tree.root, &tree.root.node)
+    }
+}
@@ -0,0 +1,161 @@
+use super::types::NodeArg;
+use crate::behaviors;
+use crate::btree::{
+    AlwaysFail, BehaviorTreeNode, Cooldown, DoUntil, Inverter, Parallel, ParallelPolicy, Repeat,
+    Selector, Sequence, Subtree, Succeeder, Timeout,
+};
+use std::collections::HashMap;
+
+/// Node that includes another tree by name, given as its one arg. It's
+/// written without children; the loader builds the named tree and passes it
+/// in as the only child.
+pub const SUBTREE: &str = "Subtree";
+
+/// How many children a node takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl std::fmt::Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arity::Exactly(k) => write!(f, "exactly {k}"),
+            Arity::AtLeast(k) => write!(f, "at least {k}"),
+        }
+    }
+}
+
+impl Arity {
+    pub fn accepts(self, n: usize) -> bool {
+        match self {
+            Arity::Exactly(k) => n == k,
+            Arity::AtLeast(k) => n >= k,
+        }
+    }
+}
+
+/// Builds a node from its (already counted) args and built children.
+/// Returns a message if an argument has the wrong type or value.
+pub type NodeBuilder = Box<
+    dyn Fn(&[NodeArg], Vec<Box<dyn BehaviorTreeNode>>) -> Result<Box<dyn BehaviorTreeNode>, String>,
+>;
+
+pub struct NodeSpec {
+    pub args: usize,
+    pub children: Arity,
+    pub build: NodeBuilder,
+}
+
+/// Node names usable in behavior tree assets.
+#[derive(Default)]
+pub struct NodeRegistry {
+    specs: HashMap<String, NodeSpec>,
+}
+
+impl NodeRegistry {
+    /// Composites and decorators from `btree` plus the agent leaves from `behaviors`.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::default();
+        register_composites(&mut registry);
+        behaviors::register_nodes(&mut registry);
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &str, args: usize, children: Arity, build: F)
+    where
+        F: Fn(
+                &[NodeArg],
+                Vec<Box<dyn BehaviorTreeNode>>,
+            ) -> Result<Box<dyn BehaviorTreeNode>, String>
+            + 'static,
+    {
+        self.specs.insert(
+            String::from(name),
+            NodeSpec {
+                args,
+                children,
+                build: Box::new(build),
+            },
+        );
+    }
+
+    /// Register a node without args or children.
+    pub fn register_leaf(&mut self, name: &str, build: fn() -> Box<dyn BehaviorTreeNode>) {
+        self.register(name, 0, Arity::Exactly(0), move |_, _| Ok(build()));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NodeSpec> {
+        self.specs.get(name)
+    }
+}
+
+pub fn text_arg(args: &[NodeArg], i: usize) -> Result<&str, String> {
+    match &args[i] {
+        NodeArg::Text(s) => Ok(s),
+        NodeArg::Int(n) => Err(format!("arg {i} must be a string, got {n}")),
+    }
+}
+
+pub fn int_arg(args: &[NodeArg], i: usize) -> Result<u64, String> {
+    match &args[i] {
+        NodeArg::Int(n) => Ok(*n),
+        NodeArg::Text(s) => Err(format!("arg {i} must be an integer, got {s:?}")),
+    }
+}
+
+fn policy_arg(args: &[NodeArg], i: usize) -> Result<ParallelPolicy, String> {
+    match text_arg(args, i)? {
+        "one" => Ok(ParallelPolicy::RequireOne),
+        "all" => Ok(ParallelPolicy::RequireAll),
+        other => Err(format!("arg {i} must be \"one\" or \"all\", got {other:?}")),
+    }
+}
+
+fn register_composites(registry: &mut NodeRegistry) {
+    registry.register("Sequence", 1, Arity::AtLeast(1), |args, children| {
+        Ok(Sequence::of(text_arg(args, 0)?, children))
+    });
+    registry.register("Selector", 1, Arity::AtLeast(1), |args, children| {
+        Ok(Selector::of(text_arg(args, 0)?, children))
+    });
+    registry.register("Parallel", 3, Arity::AtLeast(1), |args, children| {
+        Ok(Parallel::of(
+            text_arg(args, 0)?,
+            policy_arg(args, 1)?,
+            policy_arg(args, 2)?,
+            children,
+        ))
+    });
+    registry.register("DoUntil", 0, Arity::Exactly(2), |_, mut children| {
+        let action = children.pop().unwrap();
+        let condition = children.pop().unwrap();
+        Ok(DoUntil::new(condition, action))
+    });
+    registry.register("Inverter", 0, Arity::Exactly(1), |_, mut children| {
+        Ok(Inverter::new(children.pop().unwrap()))
+    });
+    registry.register("Succeeder", 0, Arity::Exactly(1), |_, mut children| {
+        Ok(Succeeder::new(children.pop().unwrap()))
+    });
+    registry.register("AlwaysFail", 0, Arity::Exactly(1), |_, mut children| {
+        Ok(AlwaysFail::new(children.pop().unwrap()))
+    });
+    registry.register("Repeat", 1, Arity::Exactly(1), |args, mut children| {
+        let times = u32::try_from(int_arg(args, 0)?).map_err(|e| e.to_string())?;
+        Ok(Repeat::times(times, children.pop().unwrap()))
+    });
+    registry.register("RepeatForever", 0, Arity::Exactly(1), |_, mut children| {
+        Ok(Repeat::forever(children.pop().unwrap()))
+    });
+    registry.register("Timeout", 1, Arity::Exactly(1), |args, mut children| {
+        Ok(Timeout::new(int_arg(args, 0)?, children.pop().unwrap()))
+    });
+    registry.register("Cooldown", 1, Arity::Exactly(1), |args, mut children| {
+        Ok(Cooldown::new(int_arg(args, 0)?, children.pop().unwrap()))
+    });
+    registry.register(SUBTREE, 1, Arity::Exactly(0), |args, mut children| {
+        Ok(Subtree::new(text_arg(args, 0)?, children.pop().unwrap()))
+    });
+}
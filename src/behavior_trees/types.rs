@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// A literal argument of a node, e.g. a sequence name or a tick count.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NodeArg {
+    Int(u64),
+    Text(String),
+}
+
+/// One node of a tree as written in the asset file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeDef {
+    pub node: String,
+    #[serde(default)]
+    pub args: Vec<NodeArg>,
+    #[serde(default)]
+    pub children: Vec<NodeDef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeDef {
+    pub name: String,
+    pub root: NodeDef,
+}
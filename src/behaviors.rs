@@ -1,28 +1,215 @@
+use crate::behavior::build_knowledge::recipe_by_id;
+use crate::behavior::goap::{GoapAction, GoapGoal, WorldState};
+use crate::behavior::production::{plan_production, ProductionPlan};
+use crate::behavior_trees::registry::{int_arg, text_arg, Arity, NodeRegistry};
+use crate::blackboard::{Blackboard, KeyUse, Value, ValueType, TARGET, TARGET_ITEM};
 use crate::btree::BehaviorStatus::{Failure, Running, Success};
-use crate::btree::{BehaviorStatus, BehaviorTreeNode, DoUntil, Sequence};
+use crate::btree::{BehaviorContext, BehaviorStatus, BehaviorTreeNode};
 use crate::components::StateType::{Idle, Move};
-use crate::components::{Food, Movement, Position, State, Stone, Wood};
+use crate::components::{Building, Hunger, Item, Movement, Path, Position, Shape, State};
 use crate::entity_commands::{CommandType, EntityCommand};
-use crate::{entity_commands, recipes_old, EntityWithType, Knowledge};
-use hecs::{Component, Entity, World as ComponentRegistry};
-use std::any::TypeId;
+use crate::items::ItemDb;
+use crate::map::Map;
+use crate::recipes::types::{ProductKind, Recipe};
+use crate::{entity_commands, pathfinding, BuildSite, Knowledge};
+use hecs::{Entity, World as ComponentRegistry};
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 pub fn do_nothing() -> Box<dyn BehaviorTreeNode> {
     Box::new(DoNothing {})
 }
 
-pub fn build_house() -> Box<Sequence> {
-    Sequence::of(
-        "build_house",
-        vec![
-            ChooseRecipe::new(),
-            // find and reserve place
-            collect_items_from_recipe(),
-            // move to position
-            // build
-        ],
-    )
+/// Leaf nodes usable from behavior tree assets, see `behavior_trees`.
+pub fn register_nodes(registry: &mut NodeRegistry) {
+    registry.register_leaf("DoNothing", do_nothing);
+    registry.register_leaf("HasAllInRecipe", || HasAllInRecipe::new());
+    registry.register_leaf("FindItemFromRecipe", || FindItemFromRecipe::new());
+    registry.register_leaf("FindNearestFood", || FindNearestFood::new());
+    registry.register_leaf("PickUpTargetToInventory", || PickUpTargetToInventory::new());
+    registry.register_leaf("MoveToSite", || MoveToSite::new());
+    registry.register_leaf("BuildOnSite", || BuildOnSite::new());
+    registry.register_leaf("EatFromInventory", || EatFromInventory::new());
+    registry.register_leaf("CraftRecipe", || CraftRecipe::new());
+    registry.register("ChooseRecipe", 1, Arity::Exactly(0), |args, _| {
+        Ok(ChooseRecipe::new(text_arg(args, 0)?))
+    });
+    registry.register("ChooseNextCraft", 1, Arity::Exactly(0), |args, _| {
+        Ok(ChooseNextCraft::new(text_arg(args, 0)?))
+    });
+    registry.register("GoapGoal", 1, Arity::Exactly(0), |args, _| {
+        goap_goal(text_arg(args, 0)?)
+    });
+    registry.register("MoveTo", 1, Arity::Exactly(0), |args, _| {
+        Ok(move_to(text_arg(args, 0)?))
+    });
+    registry.register("ReserveBuildingSite", 2, Arity::Exactly(0), |args, _| {
+        let w = u32::try_from(int_arg(args, 0)?).map_err(|e| e.to_string())?;
+        let h = u32::try_from(int_arg(args, 1)?).map_err(|e| e.to_string())?;
+        Ok(ReserveBuildingSite::new((w, h)))
+    });
+}
+
+// Reservations outlive the item collection that follows them; the TTL only
+// frees sites of agents that gave up on building.
+const SITE_RESERVATION_TTL: u32 = 60 * 60 * 5;
+
+// Facts the GOAP planner reasons about, see `agent_actions` and `sense_agent`.
+const HAS_TOOLS: &str = "has_tools";
+const HAS_RECIPE: &str = "has_recipe";
+const HAS_SITE: &str = "has_site";
+const HAS_MATERIALS: &str = "has_materials";
+const AT_SITE: &str = "at_site";
+const HAS_HOUSE: &str = "has_house";
+const HAS_FOOD: &str = "has_food";
+const HUNGRY: &str = "hungry";
+
+// Product the house facts are about.
+const HOUSE: &str = "house_basic";
+
+// Hunger value from which an agent counts as hungry.
+pub const HUNGER_THRESHOLD: u8 = 60;
+
+/// Planned behavior for the goal called `name`, for the `GoapGoal` node: the
+/// agent works out which of `agent_actions` it still needs from what it has.
+pub fn goap_goal(name: &str) -> Result<Box<dyn BehaviorTreeNode>, String> {
+    let goal = match name {
+        "have_house" => WorldState::new().with(HAS_HOUSE, true),
+        "not_hungry" => WorldState::new().with(HUNGRY, false),
+        other => {
+            return Err(format!(
+                "unknown goal {other:?}; known goals: have_house, not_hungry"
+            ))
+        }
+    };
+    Ok(GoapGoal::new(name, goal, agent_actions(), sense_agent))
+}
+
+/// The actions agents plan with. Each names the tree in the behavior assets
+/// that carries it out.
+pub fn agent_actions() -> Vec<GoapAction> {
+    vec![
+        // crafting picks the tool recipes, so the house recipe has to follow it
+        GoapAction::new("craft_tools", 4, "craft_for_house")
+            .causes(HAS_TOOLS, true)
+            .causes(HAS_RECIPE, false)
+            .causes(AT_SITE, false),
+        GoapAction::new("choose_house_recipe", 1, "choose_house_recipe").causes(HAS_RECIPE, true),
+        GoapAction::new("reserve_site", 1, "reserve_house_site")
+            .requires(HAS_RECIPE, true)
+            .causes(HAS_SITE, true),
+        GoapAction::new("collect_materials", 5, "collect_items_from_recipe")
+            .requires(HAS_RECIPE, true)
+            .causes(HAS_MATERIALS, true)
+            .causes(AT_SITE, false),
+        GoapAction::new("move_to_site", 2, "move_to_site")
+            .requires(HAS_SITE, true)
+            .causes(AT_SITE, true),
+        GoapAction::new("build", 3, "build_on_site")
+            .requires(HAS_TOOLS, true)
+            .requires(HAS_RECIPE, true)
+            .requires(HAS_SITE, true)
+            .requires(HAS_MATERIALS, true)
+            .requires(AT_SITE, true)
+            .causes(HAS_HOUSE, true)
+            .causes(HAS_SITE, false)
+            .causes(HAS_MATERIALS, false),
+        GoapAction::new("find_food", 2, "find_food")
+            .causes(HAS_FOOD, true)
+            .causes(AT_SITE, false),
+        GoapAction::new("eat", 1, "eat_from_inventory")
+            .requires(HAS_FOOD, true)
+            .causes(HUNGRY, false)
+            .causes(HAS_FOOD, false),
+    ]
+}
+
+/// Read the planner facts off the agent's knowledge and components.
+fn sense_agent(
+    knowledge: &Knowledge,
+    registry: &ComponentRegistry,
+    ctx: &BehaviorContext,
+) -> WorldState {
+    let house_recipe = knowledge
+        .recipe
+        .as_ref()
+        .filter(|recipe| recipe.product.id == HOUSE);
+    let has_materials = house_recipe.is_some_and(|recipe| {
+        recipe
+            .ingredients
+            .iter()
+            .all(|i| inventory_count(&knowledge.inventory, &i.id) >= i.qty)
+    });
+    let has_tools = plan_for(HOUSE, knowledge, ctx).is_some_and(|plan| plan.next_craft().is_none());
+    let at_site = match (&knowledge.site, registry.get::<&Position>(knowledge.own_id)) {
+        (Some(site), Ok(pos)) => {
+            let (x, y) = site.center();
+            (pos.x - x).abs() < 0.5 && (pos.y - y).abs() < 0.5
+        }
+        _ => false,
+    };
+    let hungry = registry
+        .get::<&Hunger>(knowledge.own_id)
+        .is_ok_and(|h| h.value >= HUNGER_THRESHOLD);
+
+    WorldState::new()
+        .with(HAS_TOOLS, has_tools)
+        .with(HAS_RECIPE, house_recipe.is_some())
+        .with(HAS_SITE, knowledge.site.is_some())
+        .with(HAS_MATERIALS, has_materials)
+        .with(AT_SITE, at_site)
+        .with(HAS_FOOD, food_in_inventory(knowledge, ctx.items).is_some())
+        .with(HUNGRY, hungry)
+}
+
+fn food_in_inventory(knowledge: &Knowledge, items: &ItemDb) -> Option<String> {
+    let mut ids: Vec<&String> = knowledge
+        .inventory
+        .iter()
+        .filter(|(_, entities)| !entities.is_empty())
+        .map(|(id, _)| id)
+        .filter(|id| {
+            items
+                .def_id(id)
+                .is_some_and(|def_id| items.get(def_id).has_tag("food"))
+        })
+        .collect();
+    ids.sort(); // inventory is a HashMap, keep the pick deterministic
+    ids.first().map(|id| (*id).clone())
+}
+
+/// Eats one food item from the inventory and resets hunger.
+struct EatFromInventory {}
+
+impl EatFromInventory {
+    fn new() -> Box<Self> {
+        Box::new(EatFromInventory {})
+    }
+}
+
+impl BehaviorTreeNode for EatFromInventory {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        _: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        let Some(food_id) = food_in_inventory(knowledge, ctx.items) else {
+            return Failure;
+        };
+        let foods = knowledge.inventory.get_mut(&food_id).unwrap();
+        let food = *foods.last().unwrap();
+        if registry.get::<&Position>(food).is_ok() {
+            return Running; // picked up this tick, still waiting to leave the map
+        }
+        foods.pop();
+        let _ = registry.despawn(food);
+        if let Ok(mut hunger) = registry.get::<&mut Hunger>(knowledge.own_id) {
+            hunger.value = 0;
+        }
+        Success
+    }
 }
 
 struct HasAllInRecipe {}
@@ -39,48 +226,33 @@ impl BehaviorTreeNode for HasAllInRecipe {
         knowledge: &mut Knowledge,
         _entity_commands: &mut Vec<EntityCommand>,
         _registry: &mut ComponentRegistry,
+        _: &mut BehaviorContext,
     ) -> BehaviorStatus {
-        println!("HasAllInRecipe check!");
         match &knowledge.recipe {
-            None => {
-                println!("No recipe set! HasAllInRecipe failed");
-                Failure
-            }
+            None => Failure,
             Some(recipe) => {
-                for (item_type_id, count) in &recipe.ingredients {
-                    if knowledge.inventory.get(item_type_id).is_none()
-                        || knowledge.inventory.get(item_type_id).unwrap().len() < *count
-                    {
-                        println!("Some items from recipe not collected");
+                for ingredient in &recipe.ingredients {
+                    if inventory_count(&knowledge.inventory, &ingredient.id) < ingredient.qty {
                         return Failure;
                     }
                 }
-                println!("Everything from recipe is collected");
                 Success // everything is collected
             }
         }
     }
 }
 
-pub fn collect_items_from_recipe() -> Box<dyn BehaviorTreeNode> {
-    DoUntil::new(
-        HasAllInRecipe::new(),
-        Sequence::of(
-            "collect_items_for_recipe",
-            vec![
-                FindItemFromRecipe::new(),
-                MoveToTarget::new(),
-                PickUpTargetToInventory::new(),
-            ],
-        ),
-    )
+/// Plans how to make `product_id` from what the agent holds and picks the
+/// recipe the plan makes it with. Fails if no plan gets there.
+struct ChooseRecipe {
+    product_id: String,
 }
 
-struct ChooseRecipe {}
-
 impl ChooseRecipe {
-    fn new() -> Box<Self> {
-        Box::new(ChooseRecipe {})
+    fn new(product_id: &str) -> Box<Self> {
+        Box::new(ChooseRecipe {
+            product_id: String::from(product_id),
+        })
     }
 }
 
@@ -90,10 +262,321 @@ impl BehaviorTreeNode for ChooseRecipe {
         knowledge: &mut Knowledge,
         _: &mut Vec<EntityCommand>,
         _: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        let Some(plan) = plan_for(&self.product_id, knowledge, ctx) else {
+            return Failure;
+        };
+        match plan
+            .final_recipe()
+            .and_then(|id| recipe_by_id(id, ctx.recipes))
+        {
+            None => Failure,
+            Some(recipe) => {
+                knowledge.recipe = Some(recipe.clone());
+                Success
+            }
+        }
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("ChooseRecipe({})", self.product_id))
+    }
+}
+
+/// Plans how to make `product_id` and picks the first item the plan crafts,
+/// so tools and intermediates get made before what needs them. Fails once
+/// nothing is left to craft, only gathering and the product's own recipe.
+struct ChooseNextCraft {
+    product_id: String,
+}
+
+impl ChooseNextCraft {
+    fn new(product_id: &str) -> Box<Self> {
+        Box::new(ChooseNextCraft {
+            product_id: String::from(product_id),
+        })
+    }
+}
+
+impl BehaviorTreeNode for ChooseNextCraft {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        _: &mut Vec<EntityCommand>,
+        _: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
     ) -> BehaviorStatus {
-        knowledge.recipe = Option::from(recipes_old::house());
+        let Some(plan) = plan_for(&self.product_id, knowledge, ctx) else {
+            return Failure;
+        };
+        match plan
+            .next_craft()
+            .and_then(|id| recipe_by_id(id, ctx.recipes))
+        {
+            None => Failure,
+            Some(recipe) => {
+                knowledge.recipe = Some(recipe.clone());
+                Success
+            }
+        }
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("ChooseNextCraft({})", self.product_id))
+    }
+}
+
+fn plan_for(
+    product_id: &str,
+    knowledge: &Knowledge,
+    ctx: &BehaviorContext,
+) -> Option<ProductionPlan> {
+    plan_production(product_id, &knowledge.inventory, ctx.recipes, ctx.items).ok()
+}
+
+struct ReserveBuildingSite {
+    footprint: (u32, u32),
+}
+
+impl ReserveBuildingSite {
+    fn new(footprint: (u32, u32)) -> Box<Self> {
+        Box::new(ReserveBuildingSite { footprint })
+    }
+}
+
+impl BehaviorTreeNode for ReserveBuildingSite {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        _: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        // keep the site we already hold, unless its reservation expired
+        if let Some(site) = &knowledge.site {
+            if ctx.map.renew(site.reservation, SITE_RESERVATION_TTL) {
+                return Success;
+            }
+            knowledge.site = None;
+        }
+
+        let own_pos = registry.get::<&Position>(knowledge.own_id).unwrap();
+        let own_tile = ctx.map.world_to_tile(own_pos.x, own_pos.y);
+        drop(own_pos);
+
+        let (w, h) = self.footprint;
+        let site = own_tile
+            .and_then(|near| ctx.map.find_free_rect(w, h, near))
+            .and_then(|(x, y)| {
+                let reservation = ctx.map.reserve_rect(x, y, w, h, SITE_RESERVATION_TTL)?;
+                Some(BuildSite {
+                    reservation,
+                    x,
+                    y,
+                    w,
+                    h,
+                    progress_seconds: 0.,
+                })
+            });
+
+        match site {
+            None => Failure,
+            Some(site) => {
+                knowledge.site = Some(site);
+                Success
+            }
+        }
+    }
+}
+
+struct MoveToSite {}
+
+impl MoveToSite {
+    fn new() -> Box<Self> {
+        Box::new(MoveToSite {})
+    }
+}
+
+impl BehaviorTreeNode for MoveToSite {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        _: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        let Some(site) = &knowledge.site else {
+            return Failure;
+        };
+        let destination = site.center();
+        move_along_path(knowledge.own_id, destination, 0.1, registry, ctx.map)
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        _: &mut BehaviorContext,
+    ) {
+        stop_moving(knowledge.own_id, registry);
+    }
+}
+
+/// Spends the recipe's build time on the reserved site, then consumes the
+/// ingredients from the inventory and spawns the building there. Progress is
+/// kept on the site, so an interrupted build continues where it stopped.
+struct BuildOnSite {}
+
+impl BuildOnSite {
+    fn new() -> Box<Self> {
+        Box::new(BuildOnSite {})
+    }
+}
+
+impl BehaviorTreeNode for BuildOnSite {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        _: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        let (Some(recipe), Some(site)) = (&knowledge.recipe, &mut knowledge.site) else {
+            return Failure;
+        };
+        if !holds_tools(recipe, &knowledge.inventory, ctx.items) {
+            return Failure;
+        }
+        if !ctx.map.renew(site.reservation, SITE_RESERVATION_TTL) {
+            knowledge.site = None;
+            return Failure;
+        }
+
+        site.progress_seconds += ctx.dt_seconds;
+        if site.progress_seconds * 1000. < recipe.time_ms as f32 {
+            return Running;
+        }
+
+        // check every ingredient before consuming any, so a missing one
+        // doesn't lose the others
+        let missing = recipe.ingredients.iter().any(|ingredient| {
+            inventory_count(&knowledge.inventory, &ingredient.id) < ingredient.qty
+        });
+        if missing {
+            return Failure;
+        }
+        for ingredient in &recipe.ingredients {
+            let items = knowledge
+                .inventory
+                .entry(ingredient.id.clone())
+                .or_default();
+            for item in items.drain(..ingredient.qty as usize) {
+                let _ = registry.despawn(item);
+            }
+        }
+
+        // put the building on the map
+        let (x, y) = site.center();
+        registry.spawn((
+            Position::new(x, y),
+            Shape::new(site.w as f32, site.h as f32, (140, 90, 50, 255)),
+            Building {
+                x: site.x,
+                y: site.y,
+                w: site.w,
+                h: site.h,
+            },
+        ));
+        ctx.map.set_rect_occupied(site.x, site.y, site.w, site.h);
+        ctx.map.release(site.reservation);
+        knowledge.site = None;
+
+        Success
+    }
+}
+
+/// Crafts the item recipe in `knowledge.recipe`: spends its time, then
+/// consumes the ingredients and puts the products into the inventory.
+/// Tools have to be held and are kept.
+struct CraftRecipe {
+    progress_seconds: f32,
+}
+
+impl CraftRecipe {
+    fn new() -> Box<Self> {
+        Box::new(CraftRecipe {
+            progress_seconds: 0.,
+        })
+    }
+}
+
+impl BehaviorTreeNode for CraftRecipe {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        _: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        let Some(recipe) = &knowledge.recipe else {
+            return Failure;
+        };
+        let product = ctx.items.def_id(&recipe.product.id);
+        let Some(def_id) = product.filter(|_| recipe.product.kind == ProductKind::Item) else {
+            return Failure;
+        };
+        if !holds_tools(recipe, &knowledge.inventory, ctx.items) {
+            return Failure;
+        }
+
+        self.progress_seconds += ctx.dt_seconds;
+        if self.progress_seconds * 1000. < recipe.time_ms as f32 {
+            return Running;
+        }
+        self.progress_seconds = 0.;
+
+        let missing = recipe.ingredients.iter().any(|ingredient| {
+            inventory_count(&knowledge.inventory, &ingredient.id) < ingredient.qty
+        });
+        if missing {
+            return Failure;
+        }
+        for ingredient in &recipe.ingredients {
+            let items = knowledge
+                .inventory
+                .entry(ingredient.id.clone())
+                .or_default();
+            for item in items.drain(..ingredient.qty as usize) {
+                let _ = registry.despawn(item);
+            }
+        }
+
+        // crafted items go straight into the inventory, never onto the map
+        let product_id = recipe.product.id.clone();
+        for _ in 0..recipe.product.qty {
+            let item = registry.spawn((Item { def_id },));
+            add_item_to_inventory(&mut knowledge.inventory, &product_id, item);
+        }
         Success
     }
+
+    fn reset(&mut self) {
+        self.progress_seconds = 0.;
+    }
+}
+
+/// Every tool of `recipe` is matched by a held item, by id or by tag.
+fn holds_tools(recipe: &Recipe, inventory: &HashMap<String, Vec<Entity>>, items: &ItemDb) -> bool {
+    recipe.tools.iter().all(|tool| {
+        inventory.iter().any(|(id, held)| {
+            !held.is_empty()
+                && (id == tool
+                    || items
+                        .def_id(id)
+                        .is_some_and(|def_id| items.get(def_id).has_tag(tool)))
+        })
+    })
 }
 
 struct FindItemFromRecipe {}
@@ -110,88 +593,68 @@ impl BehaviorTreeNode for FindItemFromRecipe {
         knowledge: &mut Knowledge,
         _: &mut Vec<EntityCommand>,
         registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
     ) -> BehaviorStatus {
-        println!("FindItemFromRecipe");
         match &knowledge.recipe {
-            None => {
-                println!("No recipe set! FindItemFromRecipe failed");
-                Failure
-            }
+            None => Failure,
             Some(recipe) => {
-                for (item_type_id, count) in &recipe.ingredients {
-                    let item_type_name = get_type_name(*item_type_id);
-                    if knowledge.inventory.get(item_type_id).is_none()
-                        || knowledge.inventory.get(item_type_id).unwrap().len() < *count
-                    {
-                        return match find_item_by_type_id(*item_type_id, registry) {
-                            None => {
-                                println!("Can't find item from recipe!");
-                                Failure
-                            }
+                for ingredient in &recipe.ingredients {
+                    if inventory_count(&knowledge.inventory, &ingredient.id) < ingredient.qty {
+                        return match find_item_by_id(&ingredient.id, registry, ctx.items) {
+                            None => Failure,
                             Some(item) => {
-                                println!("Found item, set target");
-                                knowledge.target =
-                                    Option::from(EntityWithType::new(*item_type_id, item));
+                                set_target(&mut knowledge.blackboard, &ingredient.id, item);
                                 Success
                             }
                         };
                     }
                 }
-                println!("Probably all items are collected in FindItemFromRecipe");
                 Failure // no new items found so it should fail? if it is success, next moveTo behavior fails because target is not updated but target entity was despawned withouth position
             }
         }
     }
-}
 
-fn get_type_name(type_id: TypeId) -> String {
-    if type_id == TypeId::of::<Food>() {
-        return String::from("Food");
-    } else if type_id == TypeId::of::<Wood>() {
-        return String::from("Wood");
-    } else if type_id == TypeId::of::<Stone>() {
-        return String::from("Stone");
+    fn keys(&self) -> Vec<KeyUse> {
+        target_writes()
     }
-    String::from("Unknown type")
 }
 
-fn find_item_by_type_id(type_id: TypeId, registry: &mut ComponentRegistry) -> Option<Entity> {
-    if type_id == TypeId::of::<Food>() {
-        return find_item::<Food>(registry);
-    } else if type_id == TypeId::of::<Wood>() {
-        return find_item::<Wood>(registry);
-    } else if type_id == TypeId::of::<Stone>() {
-        return find_item::<Stone>(registry);
-    }
-    None
+fn set_target(blackboard: &mut Blackboard, item_id: &str, item: Entity) {
+    blackboard.set(TARGET, Value::Entity(item));
+    blackboard.set(TARGET_ITEM, Value::ItemId(String::from(item_id)));
 }
 
-fn find_item<T: Component>(registry: &mut ComponentRegistry) -> Option<Entity> {
-    // find item with position
-    for (entity, (_, _)) in registry.query_mut::<(&T, &Position)>() {
-        return Some(entity);
-    }
-    None
+fn target_writes() -> Vec<KeyUse> {
+    vec![
+        KeyUse::writes(TARGET, ValueType::Entity),
+        KeyUse::writes(TARGET_ITEM, ValueType::ItemId),
+    ]
 }
 
-pub fn find_food() -> Box<Sequence> {
-    Sequence::of(
-        "find_food",
-        vec![
-            FindNearestFood::new(),
-            MoveToTarget::new(),
-            PickUpTargetToInventory::new(),
-            // consume
-        ],
-    )
+fn inventory_count(inventory: &HashMap<String, Vec<Entity>>, item_id: &str) -> u32 {
+    inventory.get(item_id).map_or(0, |items| items.len() as u32)
 }
 
-pub fn move_to_position() -> Box<dyn BehaviorTreeNode> {
-    Box::new(MoveToPosition {})
+fn find_item_by_id(
+    item_id: &str,
+    registry: &mut ComponentRegistry,
+    items: &ItemDb,
+) -> Option<Entity> {
+    let def_id = items.def_id(item_id)?;
+    // find item with position
+    registry
+        .query_mut::<(&Item, &Position)>()
+        .into_iter()
+        .find(|(_, (item, _))| item.def_id == def_id)
+        .map(|(entity, _)| entity)
 }
 
-pub fn move_to_target() -> Box<dyn BehaviorTreeNode> {
-    Box::new(MoveToTarget {})
+/// Move to whatever `key` on the blackboard holds: an entity with a
+/// position, or a position.
+pub fn move_to(key: &str) -> Box<dyn BehaviorTreeNode> {
+    Box::new(MoveTo {
+        key: String::from(key),
+    })
 }
 
 struct PickUpTargetToInventory {}
@@ -208,38 +671,41 @@ impl BehaviorTreeNode for PickUpTargetToInventory {
         knowledge: &mut Knowledge,
         commands: &mut Vec<EntityCommand>,
         _registry: &mut ComponentRegistry,
+        _: &mut BehaviorContext,
     ) -> BehaviorStatus {
-        println!("PickUpTargetToInventory");
         // if no target is set, fail
-        if knowledge.target.is_none() {
-            println!("Target is not set, cannot PickUpTargetToInventory!");
+        let (Some(target), Some(item_id)) = (
+            knowledge.blackboard.entity(TARGET),
+            knowledge.blackboard.item_id(TARGET_ITEM),
+        ) else {
             return Failure;
-        }
-
-        let target_with_type = knowledge.target.as_ref().unwrap();
+        };
 
         // add target to inventory
-        add_item_to_inventory(
-            &mut knowledge.inventory,
-            target_with_type.type_id,
-            target_with_type.entity,
-        );
+        add_item_to_inventory(&mut knowledge.inventory, item_id, target);
 
         // dispatch command to remove entity from map
-        entity_commands::emit::remove_from_map(commands, knowledge.target.as_ref().unwrap().entity);
+        entity_commands::emit::remove_from_map(commands, target);
 
         Success
     }
+
+    fn keys(&self) -> Vec<KeyUse> {
+        vec![
+            KeyUse::reads(TARGET, &[ValueType::Entity]),
+            KeyUse::reads(TARGET_ITEM, &[ValueType::ItemId]),
+        ]
+    }
 }
 
 fn add_item_to_inventory(
-    inventory: &mut HashMap<TypeId, Vec<Entity>>,
-    type_id: TypeId,
+    inventory: &mut HashMap<String, Vec<Entity>>,
+    item_id: &str,
     item: Entity,
 ) {
-    match inventory.get_mut(&type_id) {
+    match inventory.get_mut(item_id) {
         None => {
-            inventory.insert(type_id, vec![item]);
+            inventory.insert(String::from(item_id), vec![item]);
         }
         Some(entities) => {
             entities.push(item);
@@ -255,6 +721,7 @@ impl BehaviorTreeNode for DoNothing {
         _: &mut Knowledge,
         _: &mut Vec<EntityCommand>,
         _: &mut ComponentRegistry,
+        _: &mut BehaviorContext,
     ) -> BehaviorStatus {
         Running
     }
@@ -274,6 +741,7 @@ impl BehaviorTreeNode for FindNearestFood {
         knowledge: &mut Knowledge,
         _entity_commands: &mut Vec<EntityCommand>,
         registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
     ) -> BehaviorStatus {
         // find own position
         let own_pos = registry.get::<&Position>(knowledge.own_id).unwrap();
@@ -284,115 +752,155 @@ impl BehaviorTreeNode for FindNearestFood {
         // find nearest food
         let mut nearest_food = None;
         let mut smallest_distance = f32::MAX;
-        for (food_entity, (food, pos)) in registry.query_mut::<(&Food, &Position)>() {
+        for (food_entity, (item, pos)) in registry.query_mut::<(&Item, &Position)>() {
+            let def = ctx.items.get(item.def_id);
+            if !def.has_tag("food") {
+                continue;
+            }
             let dist_x = (pos.x - own_pos_x).abs();
             let dist_y = (pos.y - own_pos_y).abs();
             let dist = dist_x.hypot(dist_y);
             if dist < smallest_distance {
                 smallest_distance = dist;
-                nearest_food = Option::from(EntityWithType {
-                    type_id: food.type_id,
-                    entity: food_entity,
-                });
+                nearest_food = Some((def.id.as_str(), food_entity));
             }
         }
 
         // set target
         match nearest_food {
-            None => {
-                println!("Can't find food!");
-                Failure
-            }
-            Some(target_entity) => {
-                knowledge.target = Option::from(target_entity);
-                println!("Finished finding food");
+            None => Failure,
+            Some((item_id, food_entity)) => {
+                set_target(&mut knowledge.blackboard, item_id, food_entity);
                 Success
             }
         }
     }
-}
 
-struct MoveToPosition {}
-
-impl MoveToPosition {
-    fn new() -> Box<Self> {
-        Box::new(MoveToPosition {})
+    fn keys(&self) -> Vec<KeyUse> {
+        target_writes()
     }
 }
 
-impl BehaviorTreeNode for MoveToPosition {
+struct MoveTo {
+    key: String,
+}
+
+impl BehaviorTreeNode for MoveTo {
     fn run(
         &mut self,
         knowledge: &mut Knowledge,
         _entity_commands: &mut Vec<EntityCommand>,
         registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
     ) -> BehaviorStatus {
-        let own_pos = registry.get::<&Position>(knowledge.own_id).unwrap();
-        let mut movement = registry.get::<&mut Movement>(knowledge.own_id).unwrap();
-        let mut state = registry.get::<&mut State>(knowledge.own_id).unwrap();
+        // entities are reached when next to them, positions when on them
+        let (destination, arrive_distance) = match knowledge.blackboard.get(&self.key) {
+            Some(Value::Entity(entity)) => match registry.get::<&Position>(*entity) {
+                Ok(pos) => ((pos.x, pos.y), 0.5),
+                Err(_) => {
+                    return Failure;
+                }
+            },
+            Some(Value::Position(x, y)) => ((*x, *y), 0.1),
+            _ => {
+                return Failure;
+            }
+        };
+
+        move_along_path(
+            knowledge.own_id,
+            destination,
+            arrive_distance,
+            registry,
+            ctx.map,
+        )
+    }
 
-        // check if already arrived
-        if (own_pos.x - knowledge.destination_x).abs() < movement.distance
-            && (own_pos.y - knowledge.destination_y).abs() < movement.distance
-        {
-            state.state = Idle;
-            println!("Finished moving to position");
-            return Success;
-        }
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        _: &mut BehaviorContext,
+    ) {
+        stop_moving(knowledge.own_id, registry);
+    }
 
-        // start movement
-        state.state = Move;
-        movement.destination_x = knowledge.destination_x;
-        movement.destination_y = knowledge.destination_y;
-        movement.distance = 0.1;
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("MoveTo({})", self.key))
+    }
 
-        Running
+    fn keys(&self) -> Vec<KeyUse> {
+        vec![KeyUse::reads(
+            &self.key,
+            &[ValueType::Entity, ValueType::Position],
+        )]
     }
 }
 
-struct MoveToTarget {}
-
-impl MoveToTarget {
-    fn new() -> Box<Self> {
-        Box::new(MoveToTarget {})
+/// Shared movement step for MoveTo and MoveToSite: succeed once within
+/// `arrive_distance` of `destination`, otherwise make sure the entity follows
+/// a path there. Fails, leaving the entity idle, if no route exists.
+fn move_along_path(
+    entity: Entity,
+    destination: (f32, f32),
+    arrive_distance: f32,
+    registry: &mut ComponentRegistry,
+    map: &Map,
+) -> BehaviorStatus {
+    let own_pos = registry.get::<&Position>(entity).unwrap();
+    let (own_x, own_y) = (own_pos.x, own_pos.y);
+    drop(own_pos);
+
+    // check if already arrived
+    if (own_x - destination.0).abs() < arrive_distance
+        && (own_y - destination.1).abs() < arrive_distance
+    {
+        stop_moving(entity, registry);
+        return Success;
     }
-}
 
-impl BehaviorTreeNode for MoveToTarget {
-    fn run(
-        &mut self,
-        knowledge: &mut Knowledge,
-        _entity_commands: &mut Vec<EntityCommand>,
-        registry: &mut ComponentRegistry,
-    ) -> BehaviorStatus {
-        // check if target is set
-        if knowledge.target.is_none() {
-            println!("Target is not set, cannot execute MoveToTarget!");
-            return Failure;
+    // plan a new path if there is none for this destination yet
+    let needs_path = match registry.get::<&Path>(entity) {
+        Ok(path) => path.goal != destination,
+        Err(_) => true,
+    };
+    if needs_path {
+        let route = map
+            .world_to_tile(own_x, own_y)
+            .zip(map.world_to_tile(destination.0, destination.1))
+            .and_then(|(start, goal)| pathfinding::find_path(map, start, goal));
+        match route {
+            None => {
+                stop_moving(entity, registry);
+                return Failure;
+            }
+            Some(tiles) => {
+                // walk through tile centers, finishing on the exact destination
+                let mut waypoints: Vec<(f32, f32)> = tiles
+                    .iter()
+                    .take(tiles.len().saturating_sub(1))
+                    .map(|(x, y)| (*x as f32 + 0.5, *y as f32 + 0.5))
+                    .collect();
+                waypoints.push(destination);
+                registry
+                    .insert_one(entity, Path::new(destination, waypoints))
+                    .expect("entity to move is missing");
+            }
         }
+    }
 
-        let target_entity = knowledge.target.as_ref().unwrap().entity;
-
-        let own_pos = registry.get::<&Position>(knowledge.own_id).unwrap();
-        let target_pos = registry.get::<&Position>(target_entity).unwrap();
-        let mut movement = registry.get::<&mut Movement>(knowledge.own_id).unwrap();
-        let mut state = registry.get::<&mut State>(knowledge.own_id).unwrap();
-
-        // check if already arrived
-        if (own_pos.x - target_pos.x).abs() < movement.distance
-            && (own_pos.y - target_pos.y).abs() < movement.distance
-        {
-            state.state = Idle;
-            println!("Finished moving to target");
-            return Success;
-        }
+    // start movement
+    let mut movement = registry.get::<&mut Movement>(entity).unwrap();
+    let mut state = registry.get::<&mut State>(entity).unwrap();
+    state.state = Move;
+    movement.destination_x = destination.0;
+    movement.destination_y = destination.1;
+    movement.distance = arrive_distance;
 
-        // start movement
-        state.state = Move;
-        movement.destination_x = target_pos.x;
-        movement.destination_y = target_pos.y;
-        movement.distance = 0.5;
+    Running
+}
 
-        Running
-    }
+fn stop_moving(entity: Entity, registry: &mut ComponentRegistry) {
+    registry.get::<&mut State>(entity).unwrap().state = Idle;
+    let _ = registry.remove_one::<Path>(entity);
 }
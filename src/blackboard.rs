@@ -0,0 +1,110 @@
+use hecs::Entity;
+use std::collections::HashMap;
+use std::fmt;
+
+// Keys shared between nodes. Trees may use any other key as long as some node
+// in the tree writes it.
+/// Entity the agent is currently after, e.g. an item to pick up.
+pub const TARGET: &str = "target";
+/// Item id of `TARGET`.
+pub const TARGET_ITEM: &str = "target_item";
+/// Where a move order sends the agent.
+pub const DESTINATION: &str = "destination";
+
+/// Keys written outside of behavior trees (by entity commands), so trees may
+/// read them without writing them first.
+pub const EXTERNAL_WRITES: &[(&str, ValueType)] = &[(DESTINATION, ValueType::Position)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Entity,
+    Position,
+    ItemId,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ValueType::Entity => "entity",
+            ValueType::Position => "position",
+            ValueType::ItemId => "item id",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Entity(Entity),
+    Position(f32, f32),
+    ItemId(String),
+}
+
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Entity(_) => ValueType::Entity,
+            Value::Position(..) => ValueType::Position,
+            Value::ItemId(_) => ValueType::ItemId,
+        }
+    }
+}
+
+/// How a node uses a blackboard key, see `BehaviorTreeNode::keys`.
+/// A read lists every type the node accepts under the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyUse {
+    Reads(String, &'static [ValueType]),
+    Writes(String, ValueType),
+}
+
+impl KeyUse {
+    pub fn reads(key: &str, types: &'static [ValueType]) -> Self {
+        KeyUse::Reads(String::from(key), types)
+    }
+    pub fn writes(key: &str, ty: ValueType) -> Self {
+        KeyUse::Writes(String::from(key), ty)
+    }
+}
+
+/// Values nodes of an agent's trees pass to each other, by key.
+/// The typed getters return `None` when the key is missing or holds another type.
+#[derive(Debug, Clone, Default)]
+pub struct Blackboard {
+    values: HashMap<String, Value>,
+}
+
+impl Blackboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: &str, value: Value) {
+        self.values.insert(String::from(key), value);
+    }
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.values.remove(key)
+    }
+
+    pub fn entity(&self, key: &str) -> Option<Entity> {
+        match self.get(key)? {
+            Value::Entity(e) => Some(*e),
+            _ => None,
+        }
+    }
+    pub fn position(&self, key: &str) -> Option<(f32, f32)> {
+        match self.get(key)? {
+            Value::Position(x, y) => Some((*x, *y)),
+            _ => None,
+        }
+    }
+    pub fn item_id(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Value::ItemId(id) => Some(id),
+            _ => None,
+        }
+    }
+}
@@ -1,22 +1,101 @@
+use crate::behavior_trees::BehaviorTreeDb;
+use crate::blackboard::KeyUse;
 use crate::btree::BehaviorStatus::{Failure, Running, Success};
 use crate::entity_commands::EntityCommand;
+use crate::items::ItemDb;
+use crate::map::Map;
+use crate::node_trace::NodeTracer;
+use crate::recipes::RecipeDb;
+use crate::time::Tick;
 use crate::Knowledge;
 
 use hecs::World as ComponentRegistry;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BehaviorStatus {
     Success,
     Failure,
     Running,
 }
 
+/// World-level resources shared by every node during a tick.
+pub struct BehaviorContext<'a> {
+    pub map: &'a mut Map,
+    pub items: &'a ItemDb,
+    pub recipes: &'a RecipeDb,
+    /// Trees nodes can build sub-behaviors from, e.g. the steps of a plan.
+    pub trees: &'a BehaviorTreeDb,
+    pub dt_seconds: f32,
+    /// Tick being simulated, for nodes that measure time in ticks.
+    pub tick: Tick,
+    /// Node visits of the traced entity's behavior, see `BehaviorTreeNode::tick`.
+    pub tracer: NodeTracer,
+}
+
 pub trait BehaviorTreeNode {
     fn run(
         &mut self,
         knowledge: &mut Knowledge,
         entity_commands: &mut Vec<EntityCommand>,
         registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
     ) -> BehaviorStatus;
+
+    /// Forget any progress so the next `run` starts from the beginning.
+    /// Composites reset their children too.
+    fn reset(&mut self) {}
+
+    /// The node returned `Running` last time but won't be run again: undo side
+    /// effects of the unfinished work (e.g. stop moving), then reset.
+    /// Only called on nodes whose last `run` returned `Running`.
+    fn on_abort(
+        &mut self,
+        _knowledge: &mut Knowledge,
+        _registry: &mut ComponentRegistry,
+        _ctx: &mut BehaviorContext,
+    ) {
+        self.reset();
+    }
+
+    /// Blackboard keys this node reads and writes, checked when trees are
+    /// loaded. Composites only declare their own, not their children's.
+    fn keys(&self) -> Vec<KeyUse> {
+        Vec::new()
+    }
+
+    /// Child nodes in running order, for tools that walk a built tree.
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        Vec::new()
+    }
+
+    /// Label of the node in execution traces and exported graphs.
+    fn name(&self) -> Cow<'static, str> {
+        let path = std::any::type_name::<Self>();
+        Cow::Borrowed(path.rsplit("::").next().unwrap_or(path))
+    }
+}
+
+impl dyn BehaviorTreeNode {
+    /// Run the node and record the visit in `ctx.tracer` if it's tracing.
+    /// Composites run their children through this so whole trees show up in
+    /// traces.
+    pub fn tick(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        if !ctx.tracer.is_active() {
+            return self.run(knowledge, entity_commands, registry, ctx);
+        }
+        let visit = ctx.tracer.enter(self.name());
+        let status = self.run(knowledge, entity_commands, registry, ctx);
+        ctx.tracer.exit(visit, status);
+        status
+    }
 }
 
 pub struct DoUntil {
@@ -44,50 +123,52 @@ impl BehaviorTreeNode for DoUntil {
         knowledge: &mut Knowledge,
         entity_commands: &mut Vec<EntityCommand>,
         registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
     ) -> BehaviorStatus {
-        // if prev running status running, proceed to action
-        match self.action_status.as_ref() {
-            None => {}
-            Some(status) => {
-                match status {
-                    Running => {
-                        // actions are still running, let them continue and return Running
-                        self.action_status =
-                            Option::from(self.action.run(knowledge, entity_commands, registry));
-                        return Running;
-                    }
-                    Success => {
-                        println!("DoUntil action status is success")
-                    }
-                    _ => {}
-                }
-            }
+        // actions still running: let them continue before checking again
+        if self.action_status == Some(Running) {
+            self.action_status = Some(self.action.tick(knowledge, entity_commands, registry, ctx));
+            return Running;
         }
 
-        // run condition check
-        let condition_status = self.condition.run(knowledge, entity_commands, registry);
-        match condition_status {
+        match self
+            .condition
+            .tick(knowledge, entity_commands, registry, ctx)
+        {
             Success => {
-                // if condition success, return success
-                println!("DoUntil condition success!");
+                self.action_status = None;
                 Success
             }
-            Failure => {
-                // if condition not success, run action, remember prev running status
-                println!("DoUntil condition failure! Trying actions again");
+            // condition not met (yet): run the action, remember its status
+            Failure | Running => {
                 self.action_status =
-                    Option::from(self.action.run(knowledge, entity_commands, registry));
-                Running
-            }
-            Running => {
-                // if condition not success, run action, remember prev running status
-                println!("DoUntil running! Running actions");
-                self.action_status =
-                    Option::from(self.action.run(knowledge, entity_commands, registry));
+                    Some(self.action.tick(knowledge, entity_commands, registry, ctx));
                 Running
             }
         }
     }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.condition.as_ref(), self.action.as_ref()]
+    }
+
+    fn reset(&mut self) {
+        self.action_status = None;
+        self.condition.reset();
+        self.action.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        if self.action_status == Some(Running) {
+            self.action.on_abort(knowledge, registry, ctx);
+        }
+        self.reset();
+    }
 }
 
 pub struct Sequence {
@@ -112,15 +193,19 @@ impl BehaviorTreeNode for Sequence {
         knowledge: &mut Knowledge,
         entity_commands: &mut Vec<EntityCommand>,
         registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
     ) -> BehaviorStatus {
         let mut i = 0;
         while i < self.children.len() {
             if self.running_behavior_idx >= 0 {
                 i = self.running_behavior_idx as usize;
             }
-            let status = self.children[i].run(knowledge, entity_commands, registry);
+            let status = self.children[i].tick(knowledge, entity_commands, registry, ctx);
             match status {
-                Failure => return Failure,
+                Failure => {
+                    self.running_behavior_idx = -1; // start anew next time
+                    return Failure;
+                }
                 Success => {
                     i += 1;
                     self.running_behavior_idx = i as i32;
@@ -131,8 +216,923 @@ impl BehaviorTreeNode for Sequence {
                 }
             };
         }
-        println!("{} sequence successful!", self.name);
-        self.running_behavior_idx = 0; // reset idx to 0 to start anew
+        self.running_behavior_idx = -1; // start anew next time
         Success
     }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("Sequence({})", self.name))
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        self.children.iter().map(|c| c.as_ref()).collect()
+    }
+
+    fn reset(&mut self) {
+        self.running_behavior_idx = -1;
+        for child in &mut self.children {
+            child.reset();
+        }
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        if self.running_behavior_idx >= 0 {
+            self.children[self.running_behavior_idx as usize].on_abort(knowledge, registry, ctx);
+        }
+        self.reset();
+    }
+}
+
+/// Runs children in order until one doesn't fail: the first child that
+/// succeeds makes the selector succeed, it fails only if every child fails.
+pub struct Selector {
+    name: String,
+    children: Vec<Box<dyn BehaviorTreeNode>>,
+    running_behavior_idx: i32,
+}
+
+impl Selector {
+    pub fn of(name: &str, children: Vec<Box<dyn BehaviorTreeNode>>) -> Box<Self> {
+        Box::new(Self {
+            name: String::from(name),
+            children,
+            running_behavior_idx: -1,
+        })
+    }
+}
+
+impl BehaviorTreeNode for Selector {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        let mut i = 0;
+        if self.running_behavior_idx >= 0 {
+            i = self.running_behavior_idx as usize;
+        }
+        while i < self.children.len() {
+            match self.children[i].tick(knowledge, entity_commands, registry, ctx) {
+                Failure => i += 1,
+                Success => {
+                    self.running_behavior_idx = -1;
+                    return Success;
+                }
+                Running => {
+                    self.running_behavior_idx = i as i32;
+                    return Running;
+                }
+            }
+        }
+        self.running_behavior_idx = -1;
+        Failure
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("Selector({})", self.name))
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        self.children.iter().map(|c| c.as_ref()).collect()
+    }
+
+    fn reset(&mut self) {
+        self.running_behavior_idx = -1;
+        for child in &mut self.children {
+            child.reset();
+        }
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        if self.running_behavior_idx >= 0 {
+            self.children[self.running_behavior_idx as usize].on_abort(knowledge, registry, ctx);
+        }
+        self.reset();
+    }
+}
+
+/// How many children of a `Parallel` have to reach a status for it to count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParallelPolicy {
+    RequireOne,
+    RequireAll,
+}
+
+/// Ticks every unfinished child each run. Finished children are not run again
+/// until the parallel itself finishes, and children still running when it
+/// finishes are aborted. Failure is checked before success.
+pub struct Parallel {
+    name: String,
+    children: Vec<Box<dyn BehaviorTreeNode>>,
+    // last status of each child, `None` until it first runs
+    statuses: Vec<Option<BehaviorStatus>>,
+    success_policy: ParallelPolicy,
+    failure_policy: ParallelPolicy,
+}
+
+impl Parallel {
+    pub fn of(
+        name: &str,
+        success_policy: ParallelPolicy,
+        failure_policy: ParallelPolicy,
+        children: Vec<Box<dyn BehaviorTreeNode>>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            name: String::from(name),
+            statuses: vec![None; children.len()],
+            children,
+            success_policy,
+            failure_policy,
+        })
+    }
+
+    fn policy_met(&self, policy: ParallelPolicy, status: BehaviorStatus) -> bool {
+        let count = self.statuses.iter().filter(|s| **s == Some(status)).count();
+        match policy {
+            ParallelPolicy::RequireOne => count > 0,
+            ParallelPolicy::RequireAll => count == self.statuses.len(),
+        }
+    }
+}
+
+impl BehaviorTreeNode for Parallel {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        for (child, status) in self.children.iter_mut().zip(self.statuses.iter_mut()) {
+            if matches!(status, None | Some(Running)) {
+                *status = Some(child.tick(knowledge, entity_commands, registry, ctx));
+            }
+        }
+
+        let result = if self.policy_met(self.failure_policy, Failure) {
+            Failure
+        } else if self.policy_met(self.success_policy, Success) {
+            Success
+        } else if self.statuses.contains(&Some(Running)) {
+            return Running;
+        } else {
+            // everything finished without meeting either policy
+            Failure
+        };
+        self.on_abort(knowledge, registry, ctx);
+        result
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("Parallel({})", self.name))
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        self.children.iter().map(|c| c.as_ref()).collect()
+    }
+
+    fn reset(&mut self) {
+        self.statuses.fill(None);
+        for child in &mut self.children {
+            child.reset();
+        }
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        for (child, status) in self.children.iter_mut().zip(self.statuses.iter()) {
+            if *status == Some(Running) {
+                child.on_abort(knowledge, registry, ctx);
+            }
+        }
+        self.reset();
+    }
+}
+
+/// Swaps the child's success and failure.
+pub struct Inverter {
+    child: Box<dyn BehaviorTreeNode>,
+}
+
+impl Inverter {
+    pub fn new(child: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self { child })
+    }
+}
+
+impl BehaviorTreeNode for Inverter {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        match self.child.tick(knowledge, entity_commands, registry, ctx) {
+            Success => Failure,
+            Failure => Success,
+            Running => Running,
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn reset(&mut self) {
+        self.child.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        self.child.on_abort(knowledge, registry, ctx);
+    }
+}
+
+/// Another tree of the database, included by name; runs it as it is.
+pub struct Subtree {
+    name: String,
+    root: Box<dyn BehaviorTreeNode>,
+}
+
+impl Subtree {
+    pub fn new(name: &str, root: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self {
+            name: String::from(name),
+            root,
+        })
+    }
+}
+
+impl BehaviorTreeNode for Subtree {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        self.root.tick(knowledge, entity_commands, registry, ctx)
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.root.as_ref()]
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("Subtree({})", self.name))
+    }
+
+    fn reset(&mut self) {
+        self.root.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        self.root.on_abort(knowledge, registry, ctx);
+    }
+}
+
+/// Runs the child again after every success, one pass per run, until it
+/// succeeded `times` times (or forever). A child failure stops the repeat.
+pub struct Repeat {
+    child: Box<dyn BehaviorTreeNode>,
+    times: Option<u32>,
+    done: u32,
+    child_running: bool,
+}
+
+impl Repeat {
+    pub fn times(times: u32, child: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self {
+            child,
+            times: Some(times),
+            done: 0,
+            child_running: false,
+        })
+    }
+
+    pub fn forever(child: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self {
+            child,
+            times: None,
+            done: 0,
+            child_running: false,
+        })
+    }
+}
+
+impl BehaviorTreeNode for Repeat {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        if self.times == Some(0) {
+            return Success;
+        }
+        let status = self.child.tick(knowledge, entity_commands, registry, ctx);
+        self.child_running = status == Running;
+        match status {
+            Running => Running,
+            Failure => {
+                self.done = 0;
+                Failure
+            }
+            Success => {
+                self.done += 1;
+                if Some(self.done) == self.times {
+                    self.done = 0;
+                    Success
+                } else {
+                    Running
+                }
+            }
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        match self.times {
+            Some(times) => Cow::Owned(format!("Repeat({times})")),
+            None => Cow::Borrowed("RepeatForever"),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.done = 0;
+        self.child_running = false;
+        self.child.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        if self.child_running {
+            self.child.on_abort(knowledge, registry, ctx);
+        }
+        self.reset();
+    }
+}
+
+/// Succeeds once the child finishes, whatever its result.
+pub struct Succeeder {
+    child: Box<dyn BehaviorTreeNode>,
+}
+
+impl Succeeder {
+    pub fn new(child: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self { child })
+    }
+}
+
+impl BehaviorTreeNode for Succeeder {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        match self.child.tick(knowledge, entity_commands, registry, ctx) {
+            Running => Running,
+            _ => Success,
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn reset(&mut self) {
+        self.child.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        self.child.on_abort(knowledge, registry, ctx);
+    }
+}
+
+/// Fails once the child finishes, whatever its result.
+pub struct AlwaysFail {
+    child: Box<dyn BehaviorTreeNode>,
+}
+
+impl AlwaysFail {
+    pub fn new(child: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self { child })
+    }
+}
+
+impl BehaviorTreeNode for AlwaysFail {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        match self.child.tick(knowledge, entity_commands, registry, ctx) {
+            Running => Running,
+            _ => Failure,
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn reset(&mut self) {
+        self.child.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        self.child.on_abort(knowledge, registry, ctx);
+    }
+}
+
+/// Fails if the child is still running `ticks` sim ticks after it started.
+pub struct Timeout {
+    child: Box<dyn BehaviorTreeNode>,
+    ticks: u64,
+    started: Option<Tick>,
+}
+
+impl Timeout {
+    pub fn new(ticks: u64, child: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self {
+            child,
+            ticks,
+            started: None,
+        })
+    }
+}
+
+impl BehaviorTreeNode for Timeout {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        match self.started {
+            None => self.started = Some(ctx.tick),
+            Some(started) if ctx.tick.0 - started.0 >= self.ticks => {
+                self.child.on_abort(knowledge, registry, ctx);
+                self.started = None;
+                return Failure;
+            }
+            Some(_) => {}
+        }
+        let status = self.child.tick(knowledge, entity_commands, registry, ctx);
+        if status != Running {
+            self.started = None;
+        }
+        status
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("Timeout({})", self.ticks))
+    }
+
+    fn reset(&mut self) {
+        self.started = None;
+        self.child.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        self.child.on_abort(knowledge, registry, ctx);
+        self.started = None;
+    }
+}
+
+/// After the child finishes, fails without running it for `ticks` sim ticks.
+pub struct Cooldown {
+    child: Box<dyn BehaviorTreeNode>,
+    ticks: u64,
+    ready_at: Tick,
+    child_running: bool,
+}
+
+impl Cooldown {
+    pub fn new(ticks: u64, child: Box<dyn BehaviorTreeNode>) -> Box<Self> {
+        Box::new(Self {
+            child,
+            ticks,
+            ready_at: Tick(0),
+            child_running: false,
+        })
+    }
+}
+
+impl BehaviorTreeNode for Cooldown {
+    fn run(
+        &mut self,
+        knowledge: &mut Knowledge,
+        entity_commands: &mut Vec<EntityCommand>,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) -> BehaviorStatus {
+        if ctx.tick < self.ready_at {
+            return Failure;
+        }
+        let status = self.child.tick(knowledge, entity_commands, registry, ctx);
+        self.child_running = status == Running;
+        if status != Running {
+            self.ready_at = Tick(ctx.tick.0 + self.ticks);
+        }
+        status
+    }
+
+    fn children(&self) -> Vec<&dyn BehaviorTreeNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Owned(format!("Cooldown({})", self.ticks))
+    }
+
+    /// Keeps the cooldown timer; only the child's progress is forgotten.
+    fn reset(&mut self) {
+        self.child_running = false;
+        self.child.reset();
+    }
+
+    fn on_abort(
+        &mut self,
+        knowledge: &mut Knowledge,
+        registry: &mut ComponentRegistry,
+        ctx: &mut BehaviorContext,
+    ) {
+        if self.child_running {
+            self.child.on_abort(knowledge, registry, ctx);
+        }
+        self.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::types::ItemIndex;
+    use crate::recipes::types::RecipeIndex;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    // Returns `script` one status per run, repeating the last, and logs every
+    // run and abort. Resetting starts the script over.
+    struct Script {
+        name: &'static str,
+        script: Vec<BehaviorStatus>,
+        at: usize,
+        log: Log,
+    }
+
+    impl BehaviorTreeNode for Script {
+        fn run(
+            &mut self,
+            _: &mut Knowledge,
+            _: &mut Vec<EntityCommand>,
+            _: &mut ComponentRegistry,
+            _: &mut BehaviorContext,
+        ) -> BehaviorStatus {
+            self.log.borrow_mut().push(self.name.to_string());
+            let status = self.script[self.at.min(self.script.len() - 1)];
+            self.at += 1;
+            status
+        }
+
+        fn reset(&mut self) {
+            self.at = 0;
+        }
+
+        fn on_abort(
+            &mut self,
+            _: &mut Knowledge,
+            _: &mut ComponentRegistry,
+            _: &mut BehaviorContext,
+        ) {
+            self.log.borrow_mut().push(format!("{} aborted", self.name));
+            self.reset();
+        }
+    }
+
+    // An agent and an empty world to run nodes in.
+    struct World {
+        log: Log,
+        registry: ComponentRegistry,
+        knowledge: Knowledge,
+        map: Map,
+        items: ItemDb,
+        recipes: RecipeDb,
+        trees: BehaviorTreeDb,
+    }
+
+    impl World {
+        fn new() -> Self {
+            let mut registry = ComponentRegistry::new();
+            let agent = registry.spawn(());
+            Self {
+                log: Log::default(),
+                registry,
+                knowledge: Knowledge::new(agent),
+                map: Map::new(4, 4),
+                items: ItemDb(ItemIndex::default()),
+                recipes: RecipeDb(RecipeIndex::default()),
+                trees: BehaviorTreeDb::load_from_assets(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/assets/behaviors"
+                ))
+                .unwrap(),
+            }
+        }
+
+        fn leaf(&self, name: &'static str, script: &[BehaviorStatus]) -> Box<Script> {
+            Box::new(Script {
+                name,
+                script: script.to_vec(),
+                at: 0,
+                log: self.log.clone(),
+            })
+        }
+
+        fn run(
+            &mut self,
+            node: &mut (dyn BehaviorTreeNode + 'static),
+            tick: u64,
+        ) -> BehaviorStatus {
+            node.tick(
+                &mut self.knowledge,
+                &mut Vec::new(),
+                &mut self.registry,
+                &mut BehaviorContext {
+                    map: &mut self.map,
+                    items: &self.items,
+                    recipes: &self.recipes,
+                    trees: &self.trees,
+                    dt_seconds: 1. / 60.,
+                    tick: Tick(tick),
+                    tracer: NodeTracer::default(),
+                },
+            )
+        }
+
+        fn log(&self) -> Vec<String> {
+            self.log.borrow().clone()
+        }
+    }
+
+    #[test]
+    fn sequence_resumes_at_the_running_child() {
+        let mut w = World::new();
+        let mut seq = Sequence::of(
+            "s",
+            vec![
+                w.leaf("a", &[Success]),
+                w.leaf("b", &[Running, Success]),
+                w.leaf("c", &[Success]),
+            ],
+        );
+        assert_eq!(w.run(seq.as_mut(), 0), Running);
+        assert_eq!(w.run(seq.as_mut(), 1), Success);
+        assert_eq!(w.log(), ["a", "b", "b", "c"]);
+    }
+
+    #[test]
+    fn selector_resumes_at_the_running_child() {
+        let mut w = World::new();
+        let mut sel = Selector::of(
+            "s",
+            vec![
+                w.leaf("a", &[Failure]),
+                w.leaf("b", &[Running, Success]),
+                w.leaf("c", &[Success]),
+            ],
+        );
+        assert_eq!(w.run(sel.as_mut(), 0), Running);
+        assert_eq!(w.run(sel.as_mut(), 1), Success);
+        assert_eq!(w.log(), ["a", "b", "b"]);
+    }
+
+    #[test]
+    fn selector_fails_when_every_child_fails() {
+        let mut w = World::new();
+        let mut sel = Selector::of("s", vec![w.leaf("a", &[Failure]), w.leaf("b", &[Failure])]);
+        assert_eq!(w.run(sel.as_mut(), 0), Failure);
+        assert_eq!(w.log(), ["a", "b"]);
+    }
+
+    #[test]
+    fn parallel_require_one_success_aborts_the_rest() {
+        let mut w = World::new();
+        let mut par = Parallel::of(
+            "p",
+            ParallelPolicy::RequireOne,
+            ParallelPolicy::RequireAll,
+            vec![w.leaf("a", &[Running, Success]), w.leaf("b", &[Running])],
+        );
+        assert_eq!(w.run(par.as_mut(), 0), Running);
+        assert_eq!(w.run(par.as_mut(), 1), Success);
+        assert_eq!(w.log(), ["a", "b", "a", "b", "b aborted"]);
+    }
+
+    #[test]
+    fn parallel_require_all_success_waits_for_every_child() {
+        let mut w = World::new();
+        let mut par = Parallel::of(
+            "p",
+            ParallelPolicy::RequireAll,
+            ParallelPolicy::RequireOne,
+            vec![w.leaf("a", &[Success]), w.leaf("b", &[Running, Success])],
+        );
+        assert_eq!(w.run(par.as_mut(), 0), Running);
+        assert_eq!(w.run(par.as_mut(), 1), Success);
+        // finished children aren't run again
+        assert_eq!(w.log(), ["a", "b", "b"]);
+    }
+
+    #[test]
+    fn parallel_checks_failure_first() {
+        let mut w = World::new();
+        let mut par = Parallel::of(
+            "p",
+            ParallelPolicy::RequireOne,
+            ParallelPolicy::RequireOne,
+            vec![w.leaf("a", &[Success]), w.leaf("b", &[Failure])],
+        );
+        assert_eq!(w.run(par.as_mut(), 0), Failure);
+    }
+
+    #[test]
+    fn parallel_require_all_failure() {
+        let mut w = World::new();
+        let mut par = Parallel::of(
+            "p",
+            ParallelPolicy::RequireAll,
+            ParallelPolicy::RequireAll,
+            vec![w.leaf("a", &[Failure]), w.leaf("b", &[Running, Failure])],
+        );
+        assert_eq!(w.run(par.as_mut(), 0), Running);
+        assert_eq!(w.run(par.as_mut(), 1), Failure);
+
+        // neither policy met once everything finished is a failure too
+        let mut par = Parallel::of(
+            "p",
+            ParallelPolicy::RequireAll,
+            ParallelPolicy::RequireAll,
+            vec![w.leaf("a", &[Failure]), w.leaf("b", &[Success])],
+        );
+        assert_eq!(w.run(par.as_mut(), 0), Failure);
+    }
+
+    #[test]
+    fn inverter_swaps_success_and_failure() {
+        let mut w = World::new();
+        let mut inv = Inverter::new(w.leaf("a", &[Success, Failure, Running]));
+        assert_eq!(w.run(inv.as_mut(), 0), Failure);
+        assert_eq!(w.run(inv.as_mut(), 1), Success);
+        assert_eq!(w.run(inv.as_mut(), 2), Running);
+    }
+
+    #[test]
+    fn succeeder_and_always_fail_wait_for_the_child() {
+        let mut w = World::new();
+        let mut ok = Succeeder::new(w.leaf("a", &[Running, Failure]));
+        assert_eq!(w.run(ok.as_mut(), 0), Running);
+        assert_eq!(w.run(ok.as_mut(), 1), Success);
+        let mut fail = AlwaysFail::new(w.leaf("b", &[Running, Success]));
+        assert_eq!(w.run(fail.as_mut(), 0), Running);
+        assert_eq!(w.run(fail.as_mut(), 1), Failure);
+    }
+
+    #[test]
+    fn repeat_times() {
+        let mut w = World::new();
+        let mut rep = Repeat::times(3, w.leaf("a", &[Success]));
+        assert_eq!(w.run(rep.as_mut(), 0), Running);
+        assert_eq!(w.run(rep.as_mut(), 1), Running);
+        assert_eq!(w.run(rep.as_mut(), 2), Success);
+        assert_eq!(w.log().len(), 3);
+
+        let mut none = Repeat::times(0, w.leaf("b", &[Success]));
+        assert_eq!(w.run(none.as_mut(), 3), Success);
+        assert_eq!(w.log().len(), 3);
+    }
+
+    #[test]
+    fn repeat_stops_at_a_failure() {
+        let mut w = World::new();
+        let mut rep = Repeat::forever(w.leaf("a", &[Success, Success, Failure]));
+        assert_eq!(w.run(rep.as_mut(), 0), Running);
+        assert_eq!(w.run(rep.as_mut(), 1), Running);
+        assert_eq!(w.run(rep.as_mut(), 2), Failure);
+    }
+
+    #[test]
+    fn repeat_forever_keeps_running() {
+        let mut w = World::new();
+        let mut rep = Repeat::forever(w.leaf("a", &[Success]));
+        for tick in 0..100 {
+            assert_eq!(w.run(rep.as_mut(), tick), Running);
+        }
+    }
+
+    #[test]
+    fn timeout_fails_after_n_ticks() {
+        let mut w = World::new();
+        let mut timeout = Timeout::new(3, w.leaf("a", &[Running]));
+        for tick in 10..13 {
+            assert_eq!(w.run(timeout.as_mut(), tick), Running);
+        }
+        assert_eq!(w.run(timeout.as_mut(), 13), Failure);
+        assert_eq!(w.log(), ["a", "a", "a", "a aborted"]);
+        // the next run starts a new timeout
+        assert_eq!(w.run(timeout.as_mut(), 20), Running);
+    }
+
+    #[test]
+    fn timeout_passes_a_result_in_time() {
+        let mut w = World::new();
+        let mut timeout = Timeout::new(3, w.leaf("a", &[Running, Running, Success]));
+        assert_eq!(w.run(timeout.as_mut(), 0), Running);
+        assert_eq!(w.run(timeout.as_mut(), 1), Running);
+        assert_eq!(w.run(timeout.as_mut(), 2), Success);
+    }
+
+    #[test]
+    fn cooldown_blocks_reentry() {
+        let mut w = World::new();
+        let mut cooldown = Cooldown::new(5, w.leaf("a", &[Success]));
+        assert_eq!(w.run(cooldown.as_mut(), 0), Success);
+        for tick in 1..5 {
+            assert_eq!(w.run(cooldown.as_mut(), tick), Failure);
+        }
+        // the child didn't run while cooling down
+        assert_eq!(w.log(), ["a"]);
+        assert_eq!(w.run(cooldown.as_mut(), 5), Success);
+    }
+
+    #[test]
+    fn cooldown_reset_keeps_the_timer() {
+        let mut w = World::new();
+        let mut cooldown = Cooldown::new(5, w.leaf("a", &[Success]));
+        assert_eq!(w.run(cooldown.as_mut(), 0), Success);
+        cooldown.reset();
+        assert_eq!(w.run(cooldown.as_mut(), 1), Failure);
+        assert_eq!(w.run(cooldown.as_mut(), 5), Success);
+    }
 }
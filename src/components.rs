@@ -1,4 +1,4 @@
-use std::any::TypeId;
+use crate::items::types::ItemDefId;
 
 #[derive(PartialEq)]
 pub enum StateType {
@@ -64,12 +64,38 @@ impl Movement {
     }
 }
 
-pub struct Food {
-    pub type_id: TypeId,
+/// Waypoints produced by the pathfinder, followed by the `movement` system.
+/// `goal` is the destination the path was planned for, so callers can tell
+/// when it needs replanning.
+pub struct Path {
+    pub goal: (f32, f32),
+    pub waypoints: Vec<(f32, f32)>,
+    pub next: usize,
 }
-pub struct Stone {
-    pub type_id: TypeId,
+
+impl Path {
+    pub fn new(goal: (f32, f32), waypoints: Vec<(f32, f32)>) -> Self {
+        Self {
+            goal,
+            waypoints,
+            next: 0,
+        }
+    }
+
+    pub fn current(&self) -> Option<(f32, f32)> {
+        self.waypoints.get(self.next).copied()
+    }
+}
+
+/// A finished building and the map tiles it covers.
+pub struct Building {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
 }
-pub struct Wood {
-    pub type_id: TypeId,
+
+/// A world item, described by its definition in the `ItemDb`.
+pub struct Item {
+    pub def_id: ItemDefId,
 }
@@ -0,0 +1,196 @@
+use crate::btree::BehaviorTreeNode;
+use crate::items::ItemDb;
+use crate::recipes::types::{ProductKind, RecipeIndex};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// The tree under `root` as a top-down graph. Composites are boxes, leaves
+/// ellipses; children are ordered left to right as they run.
+pub fn tree_to_dot(name: &str, root: &dyn BehaviorTreeNode) -> String {
+    let mut out = format!("digraph {} {{\n", quote(name));
+    out.push_str("  node [fontname=\"Helvetica\"];\n");
+    out.push_str("  ordering=out;\n");
+    let mut next_id = 0;
+    write_node(&mut out, root, &mut next_id);
+    out.push_str("}\n");
+    out
+}
+
+// Writes `node` and everything below it, returns the DOT id of `node`.
+fn write_node(out: &mut String, node: &dyn BehaviorTreeNode, next_id: &mut usize) -> String {
+    let id = format!("n{next_id}");
+    *next_id += 1;
+    let children = node.children();
+    let shape = if children.is_empty() {
+        "ellipse"
+    } else {
+        "box"
+    };
+    let _ = writeln!(
+        out,
+        "  {id} [label={}, shape={shape}];",
+        quote(&node.name())
+    );
+    for child in children {
+        let child_id = write_node(out, child, next_id);
+        let _ = writeln!(out, "  {id} -> {child_id};");
+    }
+    id
+}
+
+/// Every recipe as a box between what it consumes and what it makes.
+/// Edges are labeled with quantities; tools, which are kept, get dashed edges.
+/// A tool that is a tag gets dotted edges from the items carrying it.
+pub fn recipes_to_dot(recipes: &RecipeIndex, items: &ItemDb) -> String {
+    let mut out = String::from("digraph recipes {\n");
+    out.push_str("  node [fontname=\"Helvetica\"];\n");
+    out.push_str("  rankdir=LR;\n");
+
+    let mut tags = HashSet::new();
+    let mut declared = HashSet::new();
+    let mut declare = |out: &mut String, id: &str, kind: ProductKind| {
+        if declared.insert(id.to_string()) {
+            let shape = match kind {
+                ProductKind::Item => "ellipse",
+                ProductKind::Building => "house",
+            };
+            let _ = writeln!(out, "  {} [shape={shape}];", quote(id));
+        }
+    };
+
+    for (_, recipe) in recipes.iter() {
+        let recipe_node = quote(&format!("recipe:{}", recipe.id));
+        let _ = writeln!(
+            out,
+            "  {recipe_node} [label={}, shape=box, style=rounded];",
+            quote(&recipe.id)
+        );
+
+        declare(&mut out, &recipe.product.id, recipe.product.kind);
+        let _ = writeln!(
+            out,
+            "  {recipe_node} -> {} [label=\"{}\"];",
+            quote(&recipe.product.id),
+            recipe.product.qty
+        );
+        for ingredient in &recipe.ingredients {
+            declare(&mut out, &ingredient.id, ingredient.kind);
+            let _ = writeln!(
+                out,
+                "  {} -> {recipe_node} [label=\"{}\"];",
+                quote(&ingredient.id),
+                ingredient.qty
+            );
+        }
+        for tool in &recipe.tools {
+            declare(&mut out, tool, ProductKind::Item);
+            let _ = writeln!(
+                out,
+                "  {} -> {recipe_node} [label=\"tool\", style=dashed];",
+                quote(tool)
+            );
+            if items.def_id(tool).is_none() && tags.insert(tool.clone()) {
+                for (_, def) in items.0.iter().filter(|(_, def)| def.has_tag(tool)) {
+                    declare(&mut out, &def.id, ProductKind::Item);
+                    let _ = writeln!(
+                        out,
+                        "  {} -> {} [style=dotted];",
+                        quote(&def.id),
+                        quote(tool)
+                    );
+                }
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behaviors::do_nothing;
+    use crate::btree::{Inverter, Sequence, Timeout};
+    use crate::items::types::{ItemDef, ItemIndex};
+    use crate::recipes::types::{Ingredient, Product, Recipe};
+
+    #[test]
+    fn tree_golden() {
+        let tree = Sequence::of(
+            "say \"hi\"",
+            vec![Inverter::new(do_nothing()), Timeout::new(5, do_nothing())],
+        );
+        assert_eq!(
+            tree_to_dot("greet", tree.as_ref()),
+            r#"digraph "greet" {
+  node [fontname="Helvetica"];
+  ordering=out;
+  n0 [label="Sequence(say \"hi\")", shape=box];
+  n1 [label="Inverter", shape=box];
+  n2 [label="DoNothing", shape=ellipse];
+  n1 -> n2;
+  n0 -> n1;
+  n3 [label="Timeout(5)", shape=box];
+  n4 [label="DoNothing", shape=ellipse];
+  n3 -> n4;
+  n0 -> n3;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn recipes_golden() {
+        let mut items = ItemIndex::default();
+        for (id, tags) in [("log", vec![]), ("axe", vec![String::from("cutter")])] {
+            items.insert(ItemDef {
+                id: String::from(id),
+                name: String::from(id),
+                color: (0, 0, 0, 255),
+                shape: (0.2, 0.2),
+                stack_size: 1,
+                tags,
+                spawn: None,
+            });
+        }
+        let mut recipes = RecipeIndex::default();
+        recipes.insert(Recipe {
+            id: String::from("hut/v1"),
+            product: Product {
+                kind: ProductKind::Building,
+                id: String::from("hut"),
+                qty: 1,
+            },
+            ingredients: vec![Ingredient {
+                kind: ProductKind::Item,
+                id: String::from("log"),
+                qty: 4,
+            }],
+            tools: vec![String::from("cutter")],
+            time_ms: 1000,
+            tags: Vec::new(),
+            flags: 0,
+        });
+        assert_eq!(
+            recipes_to_dot(&recipes, &ItemDb(items)),
+            r#"digraph recipes {
+  node [fontname="Helvetica"];
+  rankdir=LR;
+  "recipe:hut/v1" [label="hut/v1", shape=box, style=rounded];
+  "hut" [shape=house];
+  "recipe:hut/v1" -> "hut" [label="1"];
+  "log" [shape=ellipse];
+  "log" -> "recipe:hut/v1" [label="4"];
+  "cutter" [shape=ellipse];
+  "cutter" -> "recipe:hut/v1" [label="tool", style=dashed];
+  "axe" [shape=ellipse];
+  "axe" -> "cutter" [style=dotted];
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use anvil::{behavior_trees, dot, items, recipes};
+use std::path::{Path, PathBuf};
+
+pub fn usage() -> &'static str {
+    "Usage:
+      anvil dot tree NAME [--out FILE]
+      anvil dot recipes [--out FILE]
+
+    Writes a behavior tree from assets/behaviors, or the recipe dependency
+    graph from assets/recipes, as Graphviz DOT to FILE or stdout:
+      anvil dot tree build_house | dot -Tsvg > build_house.svg
+"
+}
+
+/// `anvil dot ...`; `args` are the arguments after `dot`.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut out: Option<PathBuf> = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(usage().to_string());
+        }
+        if let Some(val) = arg.strip_prefix("--out=") {
+            out = Some(PathBuf::from(val));
+            continue;
+        }
+        if arg == "--out" {
+            let p = it.next().ok_or("--out requires a file path".to_string())?;
+            out = Some(PathBuf::from(p));
+            continue;
+        }
+        positional.push(arg.as_str());
+    }
+
+    let graph = match positional.as_slice() {
+        ["tree", name] => {
+            let trees =
+                behavior_trees::BehaviorTreeDb::load_from_assets(Path::new("assets/behaviors"))
+                    .map_err(|e| format!("can't load behavior trees from assets: {e:#}"))?;
+            let root = trees.build(name).ok_or_else(|| {
+                format!(
+                    "no behavior tree {name:?}, known trees: {}",
+                    trees.names().join(", ")
+                )
+            })?;
+            dot::tree_to_dot(name, root.as_ref())
+        }
+        ["recipes"] => {
+            let items = items::ItemDb::load_from_assets(Path::new("assets/items"))
+                .map_err(|e| format!("can't load items from assets: {e:#}"))?;
+            let recipes = recipes::RecipeDb::load_from_assets(Path::new("assets/recipes"), &items)
+                .map_err(|e| format!("can't load recipes from assets: {e:#}"))?;
+            dot::recipes_to_dot(&recipes.0, &items)
+        }
+        _ => return Err(usage().to_string()),
+    };
+
+    match out {
+        Some(path) => std::fs::write(&path, graph).map_err(|e| format!("write {path:?}: {e}")),
+        None => {
+            print!("{graph}");
+            Ok(())
+        }
+    }
+}
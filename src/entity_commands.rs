@@ -2,10 +2,10 @@ use hecs::Entity;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::blackboard::{self, Value};
 use crate::components::Position;
-use crate::{behaviors, BehaviorList, Knowledge};
+use crate::{behaviors, BehaviorList, Knowledge, ScheduledBehavior, PRIORITY_ORDER};
 use hecs::World as ComponentRegistry;
-use sdl2::ttf::init;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CommandType {
@@ -13,6 +13,18 @@ pub enum CommandType {
     RemoveFromMap,
 }
 
+impl CommandType {
+    /// Every kind's `name`.
+    pub const NAMES: &'static [&'static str] = &["MoveToPosition", "RemoveFromMap"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandType::MoveToPosition { .. } => "MoveToPosition",
+            CommandType::RemoveFromMap => "RemoveFromMap",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityCommand {
     #[serde(with = "crate::entity_serde")]
@@ -21,7 +33,14 @@ pub struct EntityCommand {
 }
 
 impl EntityCommand {
-    fn new(entity: Entity, kind: CommandType) -> Self {
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+    pub fn kind(&self) -> &CommandType {
+        &self.kind
+    }
+
+    pub(crate) fn new(entity: Entity, kind: CommandType) -> Self {
         Self { entity, kind }
     }
 
@@ -110,6 +129,8 @@ pub mod emit {
     }
 }
 
+const MOVE_ORDER: &str = "move_order";
+
 pub fn process_commands(
     commands: &mut Vec<EntityCommand>,
     knowledges: &mut HashMap<Entity, Knowledge>,
@@ -122,13 +143,21 @@ pub fn process_commands(
                 let entity_behaviours = behaviours
                     .get_mut(&cmd.entity)
                     .expect("behaviours missing for entity");
-                entity_behaviours.insert(0, behaviors::move_to_position());
+                // a newer order just redirects a pending one
+                if !entity_behaviours.iter().any(|b| b.name == MOVE_ORDER) {
+                    entity_behaviours.push(ScheduledBehavior::new(
+                        MOVE_ORDER,
+                        PRIORITY_ORDER,
+                        behaviors::move_to(blackboard::DESTINATION),
+                    ));
+                }
 
                 let knowledge = knowledges
                     .get_mut(&cmd.entity)
                     .expect("knowledge missing for entity");
-                knowledge.destination_x = x;
-                knowledge.destination_y = y;
+                knowledge
+                    .blackboard
+                    .set(blackboard::DESTINATION, Value::Position(x, y));
             }
             CommandType::RemoveFromMap => {
                 registry
@@ -2,11 +2,25 @@ use hecs::Entity;
 use serde::de;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+// Binary formats get the entity's bits, human-readable ones (JSON) its
+// `Debug` form, e.g. "5v1", which `parse` reads back.
+
+/// Parse an entity written as `{id}v{generation}`, as `Debug` prints it.
+pub fn parse(s: &str) -> Option<Entity> {
+    let (id, generation) = s.split_once('v')?;
+    let id: u32 = id.parse().ok()?;
+    let generation: u32 = generation.parse().ok()?;
+    Entity::from_bits(u64::from(generation) << 32 | u64::from(id))
+}
+
 // ---- T = Entity -------------------------------------------------------------
 pub fn serialize<S>(e: &Entity, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
+    if s.is_human_readable() {
+        return s.collect_str(&format_args!("{e:?}"));
+    }
     // to_bits() -> NonZero<u64> in hecs 0.10.5
     s.serialize_u64(e.to_bits().get())
 }
@@ -15,6 +29,12 @@ pub fn deserialize<'de, D>(d: D) -> Result<Entity, D::Error>
 where
     D: Deserializer<'de>,
 {
+    if d.is_human_readable() {
+        let text = String::deserialize(d)?;
+        return parse(&text).ok_or_else(|| {
+            de::Error::custom(format!("invalid entity {text:?}, expected e.g. 5v1"))
+        });
+    }
     let bits: u64 = u64::deserialize(d)?;
     Entity::from_bits(bits)
         .ok_or_else(|| de::Error::custom("invalid entity bits (zero or out of range)".to_string()))
@@ -27,8 +47,10 @@ pub mod opt {
     where
         S: Serializer,
     {
+        #[derive(Serialize)]
+        struct Wrap<'a>(#[serde(with = "super")] &'a Entity);
         match e {
-            Some(ent) => s.serialize_some(&ent.to_bits().get()),
+            Some(ent) => s.serialize_some(&Wrap(ent)),
             None => s.serialize_none(),
         }
     }
@@ -36,14 +58,10 @@ pub mod opt {
     where
         D: Deserializer<'de>,
     {
-        let opt_bits: Option<u64> = Option::<u64>::deserialize(d)?;
-        opt_bits
-            .map(|bits| {
-                Entity::from_bits(bits).ok_or_else(|| {
-                    de::Error::custom("invalid entity bits (zero or out of range)".to_string())
-                })
-            })
-            .transpose()
+        #[derive(Deserialize)]
+        struct Wrap(#[serde(with = "super")] Entity);
+        let opt = Option::<Wrap>::deserialize(d)?;
+        Ok(opt.map(|Wrap(e)| e))
     }
 }
 
@@ -54,21 +72,17 @@ pub mod vec {
     where
         S: Serializer,
     {
-        let tmp: Vec<u64> = v.iter().map(|e| e.to_bits().get()).collect();
-        tmp.serialize(s)
+        #[derive(Serialize)]
+        struct Wrap<'a>(#[serde(with = "super")] &'a Entity);
+        s.collect_seq(v.iter().map(Wrap))
     }
     pub fn deserialize<'de, D>(d: D) -> Result<Vec<Entity>, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let bits_vec: Vec<u64> = Vec::<u64>::deserialize(d)?;
-        bits_vec
-            .into_iter()
-            .map(|bits| {
-                Entity::from_bits(bits).ok_or_else(|| {
-                    de::Error::custom("invalid entity bits (zero or out of range)".to_string())
-                })
-            })
-            .collect()
+        #[derive(Deserialize)]
+        struct Wrap(#[serde(with = "super")] Entity);
+        let v = Vec::<Wrap>::deserialize(d)?;
+        Ok(v.into_iter().map(|Wrap(e)| e).collect())
     }
 }
@@ -1,5 +1,6 @@
-use crate::entity_commands::{CommandType, EntityCommand};
-use crate::{entity_commands, util, Position, Properties};
+use anvil::components::Position;
+use anvil::entity_commands::EntityCommand;
+use anvil::{entity_commands, util, Properties};
 use hecs::World as ComponentRegistry;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
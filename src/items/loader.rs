@@ -0,0 +1,98 @@
+use super::types::{ItemDef, ItemIndex};
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use std::{fs, path::Path};
+
+pub fn load_dir(dir: &Path) -> Result<ItemIndex> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("read_dir {:?}", dir))? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("ron") {
+            paths.push(path);
+        }
+    }
+    // item def ids follow load order, which must not depend on the filesystem
+    paths.sort();
+
+    let mut idx = ItemIndex::default();
+    // file of each def, by def id
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        let text =
+            fs::read_to_string(&path).with_context(|| format!("read_to_string {:?}", path))?;
+        let list: Vec<ItemDef> =
+            ron::from_str(&text).with_context(|| format!("RON parse {:?}", path))?;
+        for d in list {
+            if d.stack_size == 0 {
+                bail!(
+                    "{}: item {:?}: stack_size must be > 0",
+                    path.display(),
+                    d.id
+                );
+            }
+            let id = d.id.clone();
+            if idx.insert(d).is_none() {
+                let first = idx.def_id(&id).unwrap();
+                bail!(
+                    "{}: item {:?}: duplicate id, first defined in {}",
+                    path.display(),
+                    id,
+                    files[first as usize].display()
+                );
+            }
+            files.push(path.clone());
+        }
+    }
+    Ok(idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `files` as (name, RON list of items), in a fresh directory
+    fn load(files: &[(&str, &str)]) -> Result<ItemIndex> {
+        let dir =
+            std::env::temp_dir().join(format!("anvil-{}-items-{}", std::process::id(), files[0].0));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        let idx = load_dir(&dir);
+        fs::remove_dir_all(dir).unwrap();
+        idx
+    }
+
+    fn item(id: &str, extra: &str) -> String {
+        format!("(id: {id:?}, name: {id:?}, color: (0, 0, 0, 255), shape: (0.2, 0.2), {extra})")
+    }
+
+    #[test]
+    fn stack_size_defaults_to_one() {
+        let text = format!("[{}, {}]", item("log", "stack_size: 20"), item("axe", ""));
+        let idx = load(&[("default.ron", &text)]).unwrap();
+        let stack = |id| idx.get(idx.def_id(id).unwrap()).stack_size;
+        assert_eq!((stack("log"), stack("axe")), (20, 1));
+    }
+
+    #[test]
+    fn rejects_an_empty_stack() {
+        let text = format!("[{}]", item("log", "stack_size: 0"));
+        let err = load(&[("empty.ron", &text)]).err().unwrap();
+        assert!(err.to_string().contains("stack_size must be > 0"), "{err}");
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let b = format!("[{}]", item("axe", ""));
+        let a = format!("[{}, {}]", item("log", ""), item("axe", ""));
+        let err = load(&[("dup_b.ron", &b), ("dup_a.ron", &a)]).err().unwrap();
+        let message = err.to_string();
+        assert!(
+            message.contains("dup_b.ron: item \"axe\": duplicate id, first defined in")
+                && message.ends_with("dup_a.ron"),
+            "{message}"
+        );
+    }
+}
@@ -0,0 +1,20 @@
+pub mod loader;
+pub mod types;
+
+use std::path::Path;
+use types::ItemIndex;
+
+pub struct ItemDb(pub ItemIndex);
+
+impl ItemDb {
+    pub fn load_from_assets<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let idx = loader::load_dir(dir.as_ref())?;
+        Ok(Self(idx))
+    }
+    pub fn def_id(&self, item_id: &str) -> Option<types::ItemDefId> {
+        self.0.def_id(item_id)
+    }
+    pub fn get(&self, id: types::ItemDefId) -> &types::ItemDef {
+        self.0.get(id)
+    }
+}
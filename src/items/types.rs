@@ -0,0 +1,70 @@
+use crate::map::TerrainKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Where and how many of an item the world starts with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnRule {
+    pub count: u32,
+    /// Preferred terrain; falls back to any reachable tile if none exists.
+    pub terrain: Vec<TerrainKind>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemDef {
+    pub id: String,
+    pub name: String,
+    pub color: (u8, u8, u8, u8),
+    /// Width and height in tiles.
+    pub shape: (f32, f32),
+    /// How many fit in one inventory slot.
+    #[serde(default = "default_stack_size")]
+    pub stack_size: u32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub spawn: Option<SpawnRule>,
+}
+
+fn default_stack_size() -> u32 {
+    1
+}
+
+impl ItemDef {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+pub type ItemDefId = u32;
+
+#[derive(Default)]
+pub struct ItemIndex {
+    by_id: HashMap<String, ItemDefId>,
+    defs: Vec<ItemDef>,
+}
+
+impl ItemIndex {
+    pub fn def_id(&self, item_id: &str) -> Option<ItemDefId> {
+        self.by_id.get(item_id).copied()
+    }
+    pub fn get(&self, id: ItemDefId) -> &ItemDef {
+        &self.defs[id as usize]
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (ItemDefId, &ItemDef)> {
+        self.defs
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (i as ItemDefId, d))
+    }
+    /// Add a definition and return its def id, or `None` if the id is taken.
+    pub fn insert(&mut self, d: ItemDef) -> Option<ItemDefId> {
+        if self.by_id.contains_key(&d.id) {
+            return None;
+        }
+        let id = self.defs.len() as ItemDefId;
+        self.by_id.insert(d.id.clone(), id);
+        self.defs.push(d);
+        Some(id)
+    }
+}
@@ -0,0 +1,149 @@
+pub mod behavior;
+pub mod behavior_trees;
+pub mod behaviors;
+pub mod blackboard;
+pub mod btree;
+pub mod command_bus;
+pub mod components;
+pub mod dot;
+pub mod entity_commands;
+pub mod entity_serde;
+pub mod items;
+pub mod map;
+pub mod map_gen;
+pub mod node_trace;
+pub mod pathfinding;
+pub mod recipes;
+pub mod rng;
+pub mod sim_loop;
+pub mod simulation;
+pub mod systems;
+pub mod time;
+pub mod trace;
+pub mod util;
+pub mod utility;
+pub mod world_hash;
+
+use crate::blackboard::Blackboard;
+use crate::btree::BehaviorTreeNode;
+use crate::node_trace::NodeTraceLog;
+use crate::recipes::types::Recipe;
+use crate::time::Tick;
+use crate::trace::PropsDelta;
+use crate::utility::GoalScore;
+use hecs::Entity;
+use std::collections::HashMap;
+
+pub use simulation::Simulation;
+
+/// Everything an agent wants to do. Each tick the highest priority entry runs;
+/// see `systems::run_behaviors`.
+pub type BehaviorList = Vec<ScheduledBehavior>;
+
+/// Scheduling priority, higher preempts lower.
+pub type Priority = u8;
+pub const PRIORITY_IDLE: Priority = 0;
+pub const PRIORITY_WORK: Priority = 10;
+pub const PRIORITY_NEED: Priority = 20;
+pub const PRIORITY_ORDER: Priority = 30;
+
+pub struct ScheduledBehavior {
+    pub name: String,
+    pub priority: Priority,
+    pub node: Box<dyn BehaviorTreeNode>,
+    /// Whether the last run returned `Running`, i.e. the node has to be aborted
+    /// if something else runs instead.
+    pub running: bool,
+}
+
+impl ScheduledBehavior {
+    pub fn new(name: &str, priority: Priority, node: Box<dyn BehaviorTreeNode>) -> Self {
+        Self {
+            name: String::from(name),
+            priority,
+            node,
+            running: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Properties {
+    pub quit: bool,
+    pub selected_entity: Option<Entity>,
+    pub draw_map_grid: bool,
+}
+
+pub fn props_delta(before: &Properties, after: &Properties) -> Option<PropsDelta> {
+    let mut d = PropsDelta {
+        selected_entity: None,
+        draw_map_grid: None,
+        quit: None,
+    };
+    if before.selected_entity != after.selected_entity {
+        d.selected_entity = after.selected_entity;
+    }
+    if before.draw_map_grid != after.draw_map_grid {
+        d.draw_map_grid = Some(after.draw_map_grid);
+    }
+    if before.quit != after.quit {
+        d.quit = Some(after.quit);
+    }
+    if d.selected_entity.is_some() || d.draw_map_grid.is_some() || d.quit.is_some() {
+        Some(d)
+    } else {
+        None
+    }
+}
+
+/// Map area reserved for a building, see `Map::reserve_rect`.
+pub struct BuildSite {
+    pub reservation: u64,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    /// Build time spent on the site so far.
+    pub progress_seconds: f32,
+}
+impl BuildSite {
+    pub fn center(&self) -> (f32, f32) {
+        (
+            self.x as f32 + self.w as f32 / 2.,
+            self.y as f32 + self.h as f32 / 2.,
+        )
+    }
+}
+
+pub struct Knowledge {
+    pub own_id: Entity,
+    /// Recipe the agent works on, and the site it builds on. Kept off the
+    /// blackboard since separate trees share them, e.g. the steps of a plan,
+    /// while blackboard keys are checked per tree.
+    pub recipe: Option<Recipe>,
+    pub site: Option<BuildSite>,
+    pub inventory: HashMap<String, Vec<Entity>>,
+    /// Values the nodes of the agent's trees pass to each other.
+    pub blackboard: Blackboard,
+    /// Earliest tick a failed need behavior may be scheduled again, by behavior name.
+    pub retry_at: HashMap<String, Tick>,
+    /// Goal ranking from the last decision tick, best first.
+    pub goal_scores: Vec<GoalScore>,
+    /// Node visits of the last ticks, for debugging.
+    pub node_trace: NodeTraceLog,
+}
+
+impl Knowledge {
+    pub fn new(own_id: Entity) -> Self {
+        Self {
+            own_id,
+            recipe: None,
+            site: None,
+            inventory: HashMap::new(),
+            blackboard: Blackboard::new(),
+            retry_at: HashMap::new(),
+            goal_scores: Vec::new(),
+            node_trace: NodeTraceLog::default(),
+        }
+    }
+}
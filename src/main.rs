@@ -1,93 +1,19 @@
-mod behavior;
-mod behaviors;
-mod btree;
-mod command_bus;
-mod components;
-mod entity_commands;
-mod entity_serde;
+mod dot_export;
 mod input_controller;
-mod map;
-mod recipes;
-mod recipes_old;
-mod rng;
-mod sim_loop;
-mod systems;
-mod time;
-mod trace;
-mod util;
+mod render;
+mod trace_dump;
 mod window;
-mod world_hash;
 
-use crate::btree::BehaviorTreeNode;
-use crate::components::StateType::Idle;
-use crate::components::{Food, Hunger, Movement, Position, Shape, State, Stone, Wood};
-use crate::entity_commands::EntityCommand;
-use crate::entity_commands::{process_commands, resolve_commands};
 use crate::input_controller::InputController;
-use crate::map::Map;
-use crate::recipes_old::Recipe;
-use crate::rng::{rng_for_tick, RngRun};
-use crate::systems::{hunger, movement, render_frame, run_behaviors};
-use crate::window::Window;
-use hecs::Entity;
-use hecs::World as ComponentRegistry;
-use rand::Rng;
-use std::any::TypeId;
-use std::collections::HashMap;
+use crate::render::render_frame;
+use crate::window::{ShapePalette, Window};
+use anvil::rng::RngRun;
+use anvil::sim_loop::SimLoop;
+use anvil::trace::{Checkpoint, Crash, Player, Recorder, RunMeta, TickEvents, Trailer};
+use anvil::{behavior_trees, items, props_delta, recipes, utility, Properties, Simulation};
+use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
-use trace::{Player, PropsDelta, Recorder, RunMeta, TickEvents, Trailer};
-use world_hash as wh;
-
-type BehaviorList = Vec<Box<dyn BehaviorTreeNode>>;
-
-#[derive(Copy, Clone)]
-struct Properties {
-    quit: bool,
-    selected_entity: Option<Entity>,
-    draw_map_grid: bool,
-}
-
-fn props_delta(before: &Properties, after: &Properties) -> Option<PropsDelta> {
-    let mut d = PropsDelta {
-        selected_entity: None,
-        draw_map_grid: None,
-        quit: None,
-    };
-    if before.selected_entity != after.selected_entity {
-        d.selected_entity = after.selected_entity;
-    }
-    if before.draw_map_grid != after.draw_map_grid {
-        d.draw_map_grid = Some(after.draw_map_grid);
-    }
-    if before.quit != after.quit {
-        d.quit = Some(after.quit);
-    }
-    if d.selected_entity.is_some() || d.draw_map_grid.is_some() || d.quit.is_some() {
-        Some(d)
-    } else {
-        None
-    }
-}
-
-struct EntityWithType {
-    type_id: TypeId,
-    entity: Entity,
-}
-impl EntityWithType {
-    pub fn new(type_id: TypeId, entity: Entity) -> Self {
-        Self { type_id, entity }
-    }
-}
-
-struct Knowledge {
-    own_id: Entity,
-    target: Option<EntityWithType>,
-    destination_x: f32,
-    destination_y: f32,
-    recipe: Option<Recipe>,
-    inventory: HashMap<TypeId, Vec<Entity>>,
-    param: HashMap<String, String>,
-}
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone)]
 enum Mode {
@@ -102,20 +28,44 @@ struct Cli {
     ticks: Option<u64>,
     seed: Option<u64>,   // optional; use if you want
     sim_hz: Option<u32>, // optional; use if you want
+    headless: bool,
+    trace_nodes: bool,
+    checkpoint_every: u64,
+    recover: bool,
 }
 
 fn usage() -> &'static str {
     "Usage:
-      anvil [--record FILE | --replay FILE] [--ticks N] [--seed U64] [--sim-hz HZ]
+      anvil [--record FILE | --replay FILE] [--ticks N] [--seed U64] [--sim-hz HZ] [--headless]
+            [--trace-nodes] [--checkpoint-every N] [--recover]
+      anvil dot (tree NAME | recipes) [--out FILE]
+      anvil trace FILE [--json] [--summary] [--from TICK] [--to TICK] [--entity ID]
+                       [--command KIND] [--empty] [--recover]
 
     Examples:
       anvil --record run.bin --ticks 1200
       anvil --replay=run.bin
+      anvil --headless --replay=run.bin
 
     Notes:
       --record and --replay are mutually exclusive.
       --ticks stops the sim after N fixed ticks and prints:
         FINAL end_tick=<N> world_hash=<0x...>
+      --replay runs to the recorded end tick, then compares tick and world hash
+        with the recording and exits non-zero if they differ.
+      --headless skips SDL entirely and runs fixed ticks as fast as possible.
+        It needs --ticks or --replay to know when to stop.
+      --checkpoint-every N writes the world hash to the --record file every N
+        ticks (default 60, 0 turns it off). Replays check each one and stop at
+        the first that differs.
+      --trace-nodes also writes the behavior tree nodes the selected entity
+        visits every tick to the --record file.
+      --record files are flushed every 60 ticks; if the run panics, the file
+        is ended with the tick and message of the panic. Replaying it stops
+        there and checks that the replay panics the same way; it exits zero
+        with \"REPLAY OK: crash reproduced\" if so.
+      --recover replays a --replay file that was cut short (process killed)
+        up to its last complete record.
 "
 }
 
@@ -124,6 +74,10 @@ fn parse_args() -> Result<Cli, String> {
     let mut ticks: Option<u64> = None;
     let mut seed: Option<u64> = None;
     let mut sim_hz: Option<u32> = None;
+    let mut headless = false;
+    let mut trace_nodes = false;
+    let mut checkpoint_every: u64 = 60;
+    let mut recover = false;
 
     let mut it = std::env::args().skip(1).peekable();
     while let Some(arg) = it.next() {
@@ -166,6 +120,12 @@ fn parse_args() -> Result<Cli, String> {
             );
             continue;
         }
+        if let Some(val) = arg.strip_prefix("--checkpoint-every=") {
+            checkpoint_every = val
+                .parse()
+                .map_err(|_| "Invalid --checkpoint-every value; expected u64".to_string())?;
+            continue;
+        }
         if let Some(val) = arg.strip_prefix("--sim-hz=") {
             sim_hz = Some(
                 val.parse()
@@ -220,21 +180,61 @@ fn parse_args() -> Result<Cli, String> {
                         .map_err(|_| "Invalid --sim-hz value; expected u32".to_string())?,
                 );
             }
+            "--headless" => {
+                headless = true;
+            }
+            "--checkpoint-every" => {
+                let v = it
+                    .next()
+                    .ok_or("--checkpoint-every requires a number".to_string())?;
+                checkpoint_every = v
+                    .parse()
+                    .map_err(|_| "Invalid --checkpoint-every value; expected u64".to_string())?;
+            }
+            "--trace-nodes" => {
+                trace_nodes = true;
+            }
+            "--recover" => {
+                recover = true;
+            }
             other => {
                 return Err(format!("Unknown option: {other}\n{usage}", usage = usage()));
             }
         }
     }
 
+    // Headless runs have no window to close, so they need a natural end.
+    if headless && ticks.is_none() && !matches!(mode, Mode::Replay(_)) {
+        return Err("--headless requires --ticks or --replay".into());
+    }
+    if trace_nodes && !matches!(mode, Mode::Record(_)) {
+        return Err("--trace-nodes requires --record".into());
+    }
+    if recover && !matches!(mode, Mode::Replay(_)) {
+        return Err("--recover requires --replay".into());
+    }
+
     Ok(Cli {
         mode,
         ticks,
         seed,
         sim_hz,
+        headless,
+        trace_nodes,
+        checkpoint_every,
+        recover,
     })
 }
 
 fn main() -> Result<(), String> {
+    // Subcommands
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("dot") => return dot_export::run(&args[1..]),
+        Some("trace") => return trace_dump::run(&args[1..]),
+        _ => {}
+    }
+
     // Parse CLI
     let cli = parse_args().map_err(|e| e.to_string())?;
 
@@ -243,7 +243,7 @@ fn main() -> Result<(), String> {
     if let Some(hz) = cli.sim_hz {
         sim_hz = hz;
     }
-    let mut sim = sim_loop::SimLoop::new(sim_hz);
+    let mut sim_loop = SimLoop::new(sim_hz);
 
     let mut run_seed = 0xDEADBEEFCAFEBABEu64;
     if let Some(seed) = cli.seed {
@@ -257,17 +257,29 @@ fn main() -> Result<(), String> {
     match &cli.mode {
         Mode::Record(path) => {
             let meta = RunMeta {
-                sim_hz: sim_hz as u32,
+                sim_hz,
                 seed: run_seed,
-                version: 1,
             };
-            recorder = Some(Recorder::new(&path, meta).map_err(|e| e.to_string())?);
+            let rec = Recorder::new(path, meta).map_err(|e| e.to_string())?;
+            rec.finish_on_panic();
+            recorder = Some(rec);
         }
         Mode::Replay(path) => {
-            let p = Player::new(&path).map_err(|e| e.to_string())?;
+            let p = if cli.recover {
+                Player::recover(path)
+            } else {
+                Player::new(path)
+            };
+            let p = p.map_err(|e| format!("{e:#}"))?;
+            if p.version != anvil::trace::format::FORMAT_VERSION {
+                println!(
+                    "replaying {:?}: migrated from trace format version {}",
+                    path, p.version
+                );
+            }
             if p.meta.sim_hz != sim_hz {
                 sim_hz = p.meta.sim_hz;
-                sim = sim_loop::SimLoop::new(sim_hz);
+                sim_loop = SimLoop::new(sim_hz);
             }
             run = RngRun::new(p.meta.seed);
             player = Some(p);
@@ -275,90 +287,34 @@ fn main() -> Result<(), String> {
         Mode::Normal => {}
     }
 
-    let recipes = recipes::RecipeDb::load_from_assets(std::path::Path::new("assets/recipes"))
-        .expect("can't load recipes from assets");
-
-    // SDL2 rendering and input init
-    let sdl_context = sdl2::init()?;
-    let mut window = Window::new(&sdl_context);
-    let mut input_controller = InputController::new(&sdl_context);
-
-    let mut registry = ComponentRegistry::new();
-    let map = Map::new(24, 16);
-
-    // Entities spawn
-    let mut rand = rng_for_tick(&run, 0, 42); // stream=42 "spawn"
+    let items = items::ItemDb::load_from_assets(std::path::Path::new("assets/items"))
+        .expect("can't load items from assets");
+    let recipes =
+        recipes::RecipeDb::load_from_assets(std::path::Path::new("assets/recipes"), &items)
+            .expect("can't load recipes from assets");
+    let trees =
+        behavior_trees::BehaviorTreeDb::load_from_assets(std::path::Path::new("assets/behaviors"))
+            .expect("can't load behavior trees from assets");
+    let goals =
+        utility::UtilityDb::load_from_assets(std::path::Path::new("assets/utility"), &trees)
+            .expect("can't load goals from assets");
+
+    // SDL2 rendering and input init (skipped entirely in headless mode)
+    let mut frontend: Option<(Window, InputController)> = None;
+    if !cli.headless {
+        let palette =
+            ShapePalette::load_from_file("assets/palette/tiles.ron").unwrap_or_else(|e| {
+                eprintln!("can't load tile palette from assets, using built-in one: {e:#}");
+                ShapePalette::new()
+            });
+        let sdl_context = sdl2::init()?;
+        frontend = Some((
+            Window::new(&sdl_context, palette),
+            InputController::new(&sdl_context),
+        ));
+    }
 
-    let food_to_spawn = (0..6).map(|_| {
-        let pos = Position::new(
-            rand.random_range(2..10) as f32 + 0.5,
-            rand.random_range(2..10) as f32 + 0.5,
-        );
-        let shape = Shape::new(0.2, 0.2, (150, 40, 40, 255));
-        let food = Food {
-            type_id: TypeId::of::<Food>(),
-        };
-        (pos, shape, food)
-    });
-    registry.spawn_batch(food_to_spawn);
-
-    let wood_to_spawn = (0..3).map(|_| {
-        let pos = Position::new(
-            rand.random_range(2..10) as f32 + 0.5,
-            rand.random_range(2..10) as f32 + 0.5,
-        );
-        let shape = Shape::new(0.2, 0.2, (170, 70, 0, 255));
-        (
-            pos,
-            shape,
-            Wood {
-                type_id: TypeId::of::<Wood>(),
-            },
-        )
-    });
-    registry.spawn_batch(wood_to_spawn);
-
-    let stone_to_spawn = (0..3).map(|_| {
-        let pos = Position::new(
-            rand.random_range(2..10) as f32 + 0.5,
-            rand.random_range(2..10) as f32 + 0.5,
-        );
-        let shape = Shape::new(0.2, 0.2, (170, 170, 170, 255));
-        (
-            pos,
-            shape,
-            Stone {
-                type_id: TypeId::of::<Stone>(),
-            },
-        )
-    });
-    registry.spawn_batch(stone_to_spawn);
-
-    let entity = registry.spawn((
-        Position::new(1.5, 1.5),
-        Shape::new(0.4, 0.4, (150, 150, 150, 150)),
-        Hunger::new(),
-        Movement::new(),
-        State { state: Idle },
-    ));
-
-    let mut command_bus = command_bus::CommandBus::new();
-    let mut behaviors: HashMap<Entity, BehaviorList> = HashMap::new();
-    behaviors.insert(entity, vec![behaviors::build_house()]);
-
-    let mut knowledges: HashMap<Entity, Knowledge> = HashMap::new();
-    knowledges.insert(
-        entity,
-        Knowledge {
-            own_id: entity,
-            target: None,
-            destination_x: 0.0,
-            destination_y: 0.0,
-            recipe: Option::None,
-            inventory: HashMap::new(),
-            param: Default::default(),
-        },
-    );
+    let mut sim = Simulation::new(&run, sim_hz, items, recipes, trees, goals);
 
     let mut properties = Properties {
         quit: false,
@@ -368,6 +324,9 @@ fn main() -> Result<(), String> {
 
     let cli_ticks_limit = cli.ticks;
 
+    // node visits of the selected entity, written with the next frame's events
+    let mut node_trace = Vec::new();
+
     'main: loop {
         if properties.quit {
             break 'main;
@@ -375,12 +334,18 @@ fn main() -> Result<(), String> {
 
         // ---- Pump SDL every frame so the window stays responsive
         let before_props = properties;
-        let pre_len = command_bus.incoming.len();
-        input_controller.update(&mut properties, &mut command_bus.incoming, &mut registry);
+        let pre_len = sim.command_bus.incoming.len();
+        if let Some((_, input_controller)) = &mut frontend {
+            input_controller.update(
+                &mut properties,
+                &mut sim.command_bus.incoming,
+                &mut sim.registry,
+            );
+        }
 
         // In replay, discard any live input changes to keep determinism.
         if player.is_some() {
-            command_bus.incoming.truncate(pre_len);
+            sim.command_bus.incoming.truncate(pre_len);
             properties = before_props;
         }
 
@@ -389,24 +354,29 @@ fn main() -> Result<(), String> {
             if let Some(rec) = &mut recorder {
                 let after = properties;
                 let pd = props_delta(&before_props, &after);
-                let cmds_tail = command_bus.incoming[pre_len..].to_vec(); // EntityCommand: Clone + Serialize
+                let cmds_tail = sim.command_bus.incoming[pre_len..].to_vec(); // EntityCommand: Clone + Serialize
                 let ev = TickEvents {
                     tick: sim.tick.0,
                     props: pd,
                     commands: cmds_tail,
+                    node_trace: std::mem::take(&mut node_trace),
                 };
                 rec.push(&ev).map_err(|e| e.to_string())?;
             }
         }
 
-        // ---- Fixed-step simulation
-        let steps = sim.begin_frame();
+        // ---- Fixed-step simulation (headless doesn't wait for wall-clock time)
+        let steps = if cli.headless {
+            sim_loop.max_steps_per_frame
+        } else {
+            sim_loop.begin_frame()
+        };
         for _ in 0..steps {
             let tick = sim.tick.0;
 
             // REPLAY: inject events for THIS TICK (authoritative for sim)
             if let Some(p) = &mut player {
-                for ev in p.next_for_tick(tick).map_err(|e| e.to_string())? {
+                for ev in p.next_for_tick(tick).map_err(|e| format!("{e:#}"))? {
                     if let Some(pd) = ev.props {
                         if let Some(sel) = pd.selected_entity {
                             properties.selected_entity = Some(sel);
@@ -418,30 +388,45 @@ fn main() -> Result<(), String> {
                             properties.quit = b;
                         }
                     }
-                    command_bus.incoming.extend(ev.commands.into_iter());
+                    sim.inject(ev.commands);
                 }
             }
 
-            // --- per-tick systems ---
-            command_bus.begin_tick();
-            resolve_commands(&mut command_bus.processing);
-            process_commands(
-                &mut command_bus.processing,
-                &mut knowledges,
-                &mut behaviors,
-                &mut registry,
-            );
-            run_behaviors(
-                &mut behaviors,
-                &mut knowledges,
-                &mut command_bus.incoming,
-                &mut registry,
-            );
-            movement(&mut registry);
-            hunger(sim.fixed.seconds, &mut registry);
+            // --- per-tick systems, advances the deterministic tick counter ---
+            sim.traced_entity = properties.selected_entity;
+            let crash = player
+                .as_ref()
+                .and_then(|p| p.crash.as_ref())
+                .filter(|crash| crash.tick == tick);
+            if let Some(crash) = crash {
+                return replay_crash(&mut sim, crash);
+            }
+            sim.step();
+
+            // RECORD: world hash checkpoint; REPLAY: stop at the first one that differs
+            if let Some(rec) = &mut recorder {
+                if cli.checkpoint_every > 0 && sim.tick.0.is_multiple_of(cli.checkpoint_every) {
+                    let cp = Checkpoint::new(sim.tick.0, sim.world_hash_breakdown());
+                    rec.checkpoint(&cp).map_err(|e| e.to_string())?;
+                }
+                rec.end_tick(tick).map_err(|e| e.to_string())?;
+            }
+            if let Some(p) = &mut player {
+                if let Some(cp) = p.checkpoint_at(sim.tick.0) {
+                    cp.verify(&sim.world_hash_breakdown())?;
+                }
+            }
 
-            // advance deterministic tick counter
-            sim.advance_tick();
+            if cli.trace_nodes {
+                let latest = properties
+                    .selected_entity
+                    .and_then(|e| sim.knowledges.get(&e))
+                    .and_then(|k| k.node_trace.latest())
+                    .filter(|t| t.tick == tick);
+                if let Some(trace) = latest {
+                    node_trace.push(trace.clone());
+                }
+            }
 
             // Exiting if ticks limit from CLI args reached
             if let Some(limit) = cli_ticks_limit {
@@ -450,36 +435,58 @@ fn main() -> Result<(), String> {
                 }
             }
 
-            // Auto-exit one tick after EOF in replay
+            // Replay ends where the recording did, one tick past a recorded
+            // crash (the crashed tick should panic again before that), or one
+            // tick after the last events if the recording has neither
             if let Some(p) = &player {
-                if p.eof_reached && sim.tick.0 > p.last_tick_seen {
+                let ended = match (&p.trailer, &p.crash) {
+                    (Some(trailer), _) => sim.tick.0 >= trailer.end_tick,
+                    (None, Some(crash)) => sim.tick.0 > crash.tick,
+                    (None, None) => p.eof_reached && sim.tick.0 > p.last_tick_seen,
+                };
+                if ended {
                     properties.quit = true;
                 }
             }
 
-            #[cfg(feature = "hash_debug")]
-            if sim.tick.0 % 600 == 0 {
-                let b = world_hash::world_hash_breakdown(&registry);
-                println!(
-                    "tick={} total={:#018x} pos={:#018x} hun={:#018x} sta={:#018x} food={:#018x} wood={:#018x} stone={:#018x} shape={:#018x}",
-                    sim.tick.0, b.total, b.pos, b.hun, b.sta, b.food, b.wood, b.stone, b.shape
-                );
+            // Don't run the rest of the frame's steps past the end of the run
+            if properties.quit {
+                break;
             }
         }
 
         // ---- Render once per frame
-        render_frame(&mut window, &properties, &map, &mut registry);
+        if let Some((window, _)) = &mut frontend {
+            render_frame(
+                window,
+                &properties,
+                &sim.map,
+                &sim.items,
+                &sim.knowledges,
+                &mut sim.registry,
+            );
+        }
     }
 
-    // ---- Write trailer for future strict checks
-    let final_hash = wh::world_hash(&registry);
+    // ---- Write trailer, replays are checked against it
+    let final_hash = sim.world_hash();
     let end_tick = sim.tick.0;
+    let tr = Trailer {
+        end_tick,
+        final_world_hash: final_hash,
+    };
 
-    if let Some(rec) = recorder {
-        let tr = Trailer {
-            end_tick,
-            final_world_hash: final_hash,
-        };
+    if let Some(mut rec) = recorder {
+        // node visits of the last frame weren't written with any events yet
+        if player.is_none() && !node_trace.is_empty() {
+            let ev = TickEvents {
+                tick: end_tick,
+                props: None,
+                commands: Vec::new(),
+                node_trace,
+            };
+            rec.push(&ev).map_err(|e| e.to_string())?;
+        }
         rec.finish(&tr).map_err(|e| e.to_string())?;
     }
 
@@ -488,5 +495,82 @@ fn main() -> Result<(), String> {
         sim.tick.0, final_hash
     );
 
+    if let Some(p) = &player {
+        verify_replay(p, &tr)?;
+    }
+
+    Ok(())
+}
+
+/// Step the tick a recording crashed in, which must panic the same way.
+/// The replay ends here either way, a panic leaves the sim mid-tick.
+fn replay_crash(sim: &mut Simulation, crash: &Crash) -> Result<(), String> {
+    // keep the panic's message, in the form the recorder writes it, and
+    // leave printing it to the report
+    let message = Arc::new(Mutex::new(None));
+    let slot = Arc::clone(&message);
+    std::panic::set_hook(Box::new(move |info| {
+        *slot.lock().unwrap_or_else(|e| e.into_inner()) = Some(info.to_string());
+    }));
+    let stepped = std::panic::catch_unwind(AssertUnwindSafe(|| sim.step()));
+    // back to the default hook
+    drop(std::panic::take_hook());
+
+    if stepped.is_ok() {
+        return Err(format!(
+            "REPLAY DIVERGED from recording: it crashed at tick {}, the replay didn't:\n  {}",
+            crash.tick, crash.message
+        ));
+    }
+    let replayed = message
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default();
+    if replayed != crash.message {
+        return Err(format!(
+            "REPLAY DIVERGED from recording: both crashed at tick {}, with different panics:\n  recorded: {}\n  replayed: {}",
+            crash.tick, crash.message, replayed
+        ));
+    }
+    println!("REPLAY OK: crash reproduced at tick {}", crash.tick);
+    Ok(())
+}
+
+/// Fails with a report if the replay didn't end the way the recording did.
+/// A replay stopped early (window closed, --ticks) can't be compared.
+fn verify_replay(p: &Player, replayed: &Trailer) -> Result<(), String> {
+    if let Some(crash) = &p.crash {
+        if replayed.end_tick <= crash.tick {
+            println!(
+                "REPLAY stopped at tick {} before the recorded crash at tick {}, not verified",
+                replayed.end_tick, crash.tick
+            );
+            return Ok(());
+        }
+        return Err(format!(
+            "REPLAY DIVERGED from recording: it crashed at tick {}, the replay didn't:\n  {}",
+            crash.tick, crash.message
+        ));
+    }
+    if let Some(at) = p.cut_at() {
+        println!(
+            "REPLAY recovered: recording cut short at byte {at}, replayed to tick {}, checkpoints up to there matched",
+            replayed.end_tick
+        );
+        return Ok(());
+    }
+    let Some(recorded) = &p.trailer else {
+        return Err("recording has no trailer (cut short?), replay can't be verified".into());
+    };
+    if replayed.end_tick < recorded.end_tick {
+        println!(
+            "REPLAY stopped at tick {} before the recorded end at tick {}, not verified",
+            replayed.end_tick, recorded.end_tick
+        );
+        return Ok(());
+    }
+    recorded.verify(replayed)?;
+    println!("REPLAY OK end_tick={} matches recording", replayed.end_tick);
     Ok(())
 }
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TerrainKind {
     Unknown,
     Grass,
@@ -11,6 +13,21 @@ pub enum TerrainKind {
     Mud,
 }
 
+impl TerrainKind {
+    /// Default palette entry for this terrain, see `assets/palette/tiles.ron`.
+    pub fn shape_id(self) -> u16 {
+        match self {
+            TerrainKind::Unknown => 0,
+            TerrainKind::Grass => 1,
+            TerrainKind::Rock => 2,
+            TerrainKind::Sand => 3,
+            TerrainKind::Water => 4,
+            TerrainKind::Road => 5,
+            TerrainKind::Mud => 6,
+        }
+    }
+}
+
 #[derive(PartialEq)]
 pub struct TileVisual {
     pub shape_id: u16,
@@ -43,6 +60,7 @@ pub struct Map {
     pub height: u32,
     nodes: Vec<Tile>,
     reservations: HashMap<u64, Reservation>,
+    next_reservation_id: u64,
     dirty_tiles: HashSet<usize>,
 }
 
@@ -63,13 +81,14 @@ impl Map {
                 })
                 .collect(),
             reservations: HashMap::new(),
+            next_reservation_id: 1,
             dirty_tiles: HashSet::new(),
         }
     }
 
     #[inline]
     pub fn tile_at_pos(&self, pos_x: u32, pos_y: u32) -> &Tile {
-        &self.nodes[((pos_x as usize * self.len()) + pos_y as usize).clamp(0, self.len() - 1)]
+        &self.nodes[self.idx_xy(pos_x, pos_y).clamp(0, self.len() - 1)]
     }
 
     #[inline]
@@ -85,6 +104,20 @@ impl Map {
         (y * self.width + x) as usize
     }
     #[inline]
+    pub fn xy_idx(&self, i: usize) -> (u32, u32) {
+        (i as u32 % self.width, i as u32 / self.width)
+    }
+    #[inline]
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+    /// Tile containing the world position, if it lies on the map.
+    #[inline]
+    pub fn world_to_tile(&self, x: f32, y: f32) -> Option<(u32, u32)> {
+        let (tx, ty) = (x.floor() as i32, y.floor() as i32);
+        self.in_bounds(tx, ty).then_some((tx as u32, ty as u32))
+    }
+    #[inline]
     pub fn len(&self) -> usize {
         self.nodes.len()
     }
@@ -133,4 +166,219 @@ impl Map {
             self.mark_tile_dirty(i);
         }
     }
+
+    /// Mark a rect as covered by a building: occupied, and no longer walkable or buildable.
+    pub fn set_rect_occupied(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let xmax = (x + w).min(self.width);
+        let ymax = (y + h).min(self.height);
+        for ty in y..ymax {
+            for tx in x..xmax {
+                let i = self.idx_xy(tx, ty);
+                let tile = &mut self.nodes[i];
+                tile.occupied = true;
+                tile.passable = false;
+                tile.buildable = false;
+            }
+        }
+        self.mark_rect_dirty(x, y, w, h);
+    }
+
+    /// True if any live reservation covers the tile.
+    pub fn is_reserved(&self, x: u32, y: u32) -> bool {
+        let (x, y) = (x as i32, y as i32);
+        self.reservations
+            .values()
+            .any(|r| x >= r.x && x < r.x + r.w && y >= r.y && y < r.y + r.h)
+    }
+
+    /// True if the whole rect is on the map, buildable, unoccupied and unreserved.
+    pub fn is_rect_free(&self, x: u32, y: u32, w: u32, h: u32) -> bool {
+        if x + w > self.width || y + h > self.height {
+            return false;
+        }
+        for ty in y..y + h {
+            for tx in x..x + w {
+                let tile = self.tile_at_pos(tx, ty);
+                if !tile.buildable || tile.occupied || self.is_reserved(tx, ty) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Reserve a free `w` x `h` rect for `ttl` ticks. Returns the reservation id,
+    /// or `None` if any tile of the rect is not free.
+    pub fn reserve_rect(&mut self, x: u32, y: u32, w: u32, h: u32, ttl: u32) -> Option<u64> {
+        if !self.is_rect_free(x, y, w, h) {
+            return None;
+        }
+        let id = self.next_reservation_id;
+        self.next_reservation_id += 1;
+        self.reservations.insert(
+            id,
+            Reservation {
+                id,
+                x: x as i32,
+                y: y as i32,
+                w: w as i32,
+                h: h as i32,
+                ttl,
+            },
+        );
+        self.mark_rect_dirty(x, y, w, h);
+        Some(id)
+    }
+
+    /// Drop a reservation. Returns false if it was already released or expired.
+    pub fn release(&mut self, id: u64) -> bool {
+        match self.reservations.remove(&id) {
+            None => false,
+            Some(r) => {
+                self.mark_rect_dirty(r.x as u32, r.y as u32, r.w as u32, r.h as u32);
+                true
+            }
+        }
+    }
+
+    /// Reset a live reservation's TTL. Returns false if it no longer exists.
+    pub fn renew(&mut self, id: u64, ttl: u32) -> bool {
+        match self.reservations.get_mut(&id) {
+            None => false,
+            Some(r) => {
+                r.ttl = ttl;
+                true
+            }
+        }
+    }
+
+    /// Age all reservations by one tick and drop the expired ones.
+    pub fn tick_reservations(&mut self) {
+        let mut expired: Vec<u64> = Vec::new();
+        for r in self.reservations.values_mut() {
+            r.ttl = r.ttl.saturating_sub(1);
+            if r.ttl == 0 {
+                expired.push(r.id);
+            }
+        }
+        expired.sort_unstable();
+        for id in expired {
+            self.release(id);
+        }
+    }
+
+    /// Top-left corner of the free `w` x `h` rect whose center is closest to `near`.
+    /// Ties break on row, then column, so the choice is stable across runs.
+    pub fn find_free_rect(&self, w: u32, h: u32, near: (u32, u32)) -> Option<(u32, u32)> {
+        if w == 0 || h == 0 {
+            return None;
+        }
+        let mut best: Option<(u64, u32, u32)> = None;
+        for y in 0..self.height.saturating_sub(h - 1) {
+            for x in 0..self.width.saturating_sub(w - 1) {
+                if !self.is_rect_free(x, y, w, h) {
+                    continue;
+                }
+                // doubled coordinates keep rect centers integral
+                let dx = (2 * x + w).abs_diff(2 * near.0 + 1) as u64;
+                let dy = (2 * y + h).abs_diff(2 * near.1 + 1) as u64;
+                let key = (dx * dx + dy * dy, y, x);
+                if best.is_none_or(|b| key < b) {
+                    best = Some(key);
+                }
+            }
+        }
+        best.map(|(_, y, x)| (x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_and_release() {
+        let mut map = Map::new(8, 8);
+        let id = map.reserve_rect(2, 2, 2, 2, 10).unwrap();
+        assert!(map.is_reserved(2, 2) && map.is_reserved(3, 3));
+        assert!(!map.is_reserved(4, 3));
+        // any overlap is refused, a neighbour is fine
+        assert_eq!(map.reserve_rect(3, 3, 2, 2, 10), None);
+        let other = map.reserve_rect(4, 2, 2, 2, 10).unwrap();
+        assert_ne!(id, other);
+
+        assert!(map.release(id));
+        assert!(!map.release(id));
+        assert!(!map.is_reserved(2, 2));
+        assert!(map.reserve_rect(3, 3, 1, 1, 10).is_some());
+    }
+
+    #[test]
+    fn reservations_expire_on_their_last_tick() {
+        let mut map = Map::new(4, 4);
+        let id = map.reserve_rect(0, 0, 1, 1, 3).unwrap();
+        map.tick_reservations();
+        map.tick_reservations();
+        assert!(map.is_reserved(0, 0));
+        map.tick_reservations();
+        assert!(!map.is_reserved(0, 0));
+        assert!(!map.renew(id, 3));
+        assert!(!map.release(id));
+    }
+
+    #[test]
+    fn renew_extends_the_ttl() {
+        let mut map = Map::new(4, 4);
+        let id = map.reserve_rect(0, 0, 1, 1, 2).unwrap();
+        map.tick_reservations();
+        assert!(map.renew(id, 2));
+        map.tick_reservations();
+        assert!(map.is_reserved(0, 0));
+        map.tick_reservations();
+        assert!(!map.is_reserved(0, 0));
+    }
+
+    #[test]
+    fn is_rect_free() {
+        let mut map = Map::new(6, 6);
+        assert!(map.is_rect_free(0, 0, 6, 6));
+        // off the map
+        assert!(!map.is_rect_free(4, 4, 3, 1));
+        map.set_rect_occupied(1, 1, 1, 1);
+        assert!(!map.is_rect_free(0, 0, 2, 2));
+        assert!(map.is_rect_free(2, 0, 2, 2));
+        let i = map.idx_xy(5, 5);
+        map.tile_at_index_mut(i).buildable = false;
+        assert!(!map.is_rect_free(4, 4, 2, 2));
+        map.reserve_rect(2, 2, 2, 2, 5).unwrap();
+        assert!(!map.is_rect_free(3, 3, 1, 1));
+    }
+
+    #[test]
+    fn find_free_rect_skips_taken_tiles() {
+        let mut map = Map::new(8, 8);
+        // four rects are as close to tile (3, 3), ties go to the first row and column
+        assert_eq!(map.find_free_rect(2, 2, (3, 3)), Some((2, 2)));
+        map.reserve_rect(2, 2, 2, 2, 5).unwrap();
+        let (x, y) = map.find_free_rect(2, 2, (3, 3)).unwrap();
+        assert!(map.is_rect_free(x, y, 2, 2));
+        assert!(map.reserve_rect(x, y, 2, 2, 5).is_some());
+        assert_eq!(map.find_free_rect(0, 2, (3, 3)), None);
+        assert_eq!(map.find_free_rect(9, 1, (3, 3)), None);
+    }
+
+    #[test]
+    fn find_free_rect_is_stable() {
+        let build = || {
+            let mut map = Map::new(10, 10);
+            map.set_rect_occupied(4, 4, 2, 2);
+            map.reserve_rect(2, 5, 2, 2, 5).unwrap();
+            map
+        };
+        let first = build().find_free_rect(2, 2, (4, 4));
+        assert!(first.is_some());
+        for _ in 0..5 {
+            assert_eq!(build().find_free_rect(2, 2, (4, 4)), first);
+        }
+    }
 }
@@ -0,0 +1,170 @@
+use crate::map::{Map, TerrainKind};
+use crate::rng::{rng_for_tick, RngRun};
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::VecDeque;
+
+/// Dedicated RNG stream for terrain, so map generation never shifts other streams.
+pub const TERRAIN_STREAM: u64 = 7; // stream=7 "terrain"
+
+// Lattice spacing of the value noise, in tiles.
+const NOISE_CELL: u32 = 4;
+
+impl TerrainKind {
+    /// (passable, buildable, cost_mod) for a freshly generated tile of this terrain.
+    pub fn properties(self) -> (bool, bool, u8) {
+        match self {
+            TerrainKind::Unknown => (true, true, 0),
+            TerrainKind::Grass => (true, true, 0),
+            TerrainKind::Rock => (true, false, 2),
+            TerrainKind::Sand => (true, true, 1),
+            TerrainKind::Water => (false, false, 0),
+            TerrainKind::Road => (true, false, 0),
+            TerrainKind::Mud => (true, false, 2),
+        }
+    }
+}
+
+/// Generate a `width` x `height` map from the run seed.
+/// Terrain comes from two value-noise layers (elevation and moisture) plus one
+/// road crossing the map. Same seed, same map, tile for tile.
+pub fn generate(width: u32, height: u32, run: &RngRun) -> Map {
+    let mut rand = rng_for_tick(run, 0, TERRAIN_STREAM);
+    let elevation = value_noise(&mut rand, width, height);
+    let moisture = value_noise(&mut rand, width, height);
+    let road_y = rand.random_range(0..height);
+
+    let mut map = Map::new(width, height);
+    for i in 0..map.len() {
+        let (_, y) = map.xy_idx(i);
+        let terrain = if elevation[i] < 0.25 {
+            TerrainKind::Water
+        } else if y == road_y {
+            TerrainKind::Road
+        } else if elevation[i] < 0.32 {
+            TerrainKind::Sand
+        } else if elevation[i] > 0.72 {
+            TerrainKind::Rock
+        } else if moisture[i] > 0.7 {
+            TerrainKind::Mud
+        } else {
+            TerrainKind::Grass
+        };
+
+        let (passable, buildable, cost_mod) = terrain.properties();
+        let tile = map.tile_at_index_mut(i);
+        tile.terrain = terrain;
+        tile.passable = passable;
+        tile.buildable = buildable;
+        tile.cost_mod = cost_mod;
+        tile.visual.shape_id = terrain.shape_id();
+    }
+    map
+}
+
+/// Smoothly interpolated random lattice values in [0, 1), one per tile.
+fn value_noise(rand: &mut ChaCha8Rng, width: u32, height: u32) -> Vec<f32> {
+    let lattice_w = width / NOISE_CELL + 2;
+    let lattice_h = height / NOISE_CELL + 2;
+    let lattice: Vec<f32> = (0..lattice_w * lattice_h)
+        .map(|_| rand.random::<f32>())
+        .collect();
+    let at = |lx: u32, ly: u32| lattice[(ly * lattice_w + lx) as usize];
+
+    let mut out = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (lx, ly) = (x / NOISE_CELL, y / NOISE_CELL);
+            let tx = smoothstep((x % NOISE_CELL) as f32 / NOISE_CELL as f32);
+            let ty = smoothstep((y % NOISE_CELL) as f32 / NOISE_CELL as f32);
+            let top = lerp(at(lx, ly), at(lx + 1, ly), tx);
+            let bottom = lerp(at(lx, ly + 1), at(lx + 1, ly + 1), tx);
+            out.push(lerp(top, bottom, ty));
+        }
+    }
+    out
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Passable tile nearest to `(x, y)`, scanning rings outward in a fixed order.
+pub fn nearest_passable(map: &Map, x: u32, y: u32) -> Option<(u32, u32)> {
+    let max_radius = map.width.max(map.height) as i32;
+    for r in 0..=max_radius {
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if dx.abs() != r && dy.abs() != r {
+                    continue; // only the ring itself
+                }
+                let (tx, ty) = (x as i32 + dx, y as i32 + dy);
+                if map.in_bounds(tx, ty) && map.tile_at_pos(tx as u32, ty as u32).passable {
+                    return Some((tx as u32, ty as u32));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Tiles reachable on foot from `(x, y)`, in map index order.
+pub fn reachable_tiles(map: &Map, x: u32, y: u32) -> Vec<(u32, u32)> {
+    let mut seen = vec![false; map.len()];
+    let mut queue = VecDeque::new();
+    seen[map.idx_xy(x, y)] = true;
+    queue.push_back((x as i32, y as i32));
+
+    while let Some((cx, cy)) = queue.pop_front() {
+        for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+            let (nx, ny) = (cx + dx, cy + dy);
+            if !map.in_bounds(nx, ny) {
+                continue;
+            }
+            let i = map.idx_xy(nx as u32, ny as u32);
+            if !seen[i] && map.tile_at_index(i).passable {
+                seen[i] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    (0..map.len())
+        .filter(|i| seen[*i])
+        .map(|i| map.xy_idx(i))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // terrain of every tile, and the tiles reachable from the usual start
+    fn generated(seed: u64) -> (Vec<TerrainKind>, Vec<(u32, u32)>) {
+        let map = generate(24, 16, &RngRun::new(seed));
+        let terrain = (0..map.len())
+            .map(|i| map.tile_at_index(i).terrain)
+            .collect();
+        let (x, y) = nearest_passable(&map, 1, 1).unwrap();
+        (terrain, reachable_tiles(&map, x, y))
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        for seed in [0, 1, 0xdead_beef] {
+            assert_eq!(generated(seed), generated(seed), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn other_seed_other_map() {
+        let (terrain, reachable) = generated(1);
+        let (other_terrain, other_reachable) = generated(2);
+        assert_ne!(terrain, other_terrain);
+        assert_ne!(reachable, other_reachable);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::btree::BehaviorStatus;
+use hecs::Entity;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Ticks of node visits kept per entity, 2 seconds at 60 Hz.
+pub const NODE_TRACE_TICKS: usize = 120;
+
+/// One node run during a tick, `depth` levels below the behavior's root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeVisit {
+    pub depth: u16,
+    pub name: String,
+    pub status: BehaviorStatus,
+}
+
+/// The nodes visited while running an entity's behavior for one tick, in the
+/// order they were entered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TickTrace {
+    pub tick: u64,
+    #[serde(with = "crate::entity_serde")]
+    pub entity: Entity,
+    pub behavior: String,
+    pub visits: Vec<NodeVisit>,
+}
+
+impl fmt::Display for TickTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tick {} {}", self.tick, self.behavior)?;
+        for visit in &self.visits {
+            let indent = "  ".repeat(visit.depth as usize + 1);
+            write!(f, "\n{indent}{} {:?}", visit.name, visit.status)?;
+        }
+        Ok(())
+    }
+}
+
+/// The last `NODE_TRACE_TICKS` tick traces of an entity, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct NodeTraceLog {
+    ticks: VecDeque<TickTrace>,
+}
+
+impl NodeTraceLog {
+    pub fn push(&mut self, trace: TickTrace) {
+        if self.ticks.len() == NODE_TRACE_TICKS {
+            self.ticks.pop_front();
+        }
+        self.ticks.push_back(trace);
+    }
+
+    pub fn latest(&self) -> Option<&TickTrace> {
+        self.ticks.back()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TickTrace> {
+        self.ticks.iter()
+    }
+}
+
+/// Collects node visits while a behavior runs, see `BehaviorTreeNode::tick`.
+/// Only the behaviors of one entity are traced, visits of the others would
+/// only be thrown away.
+#[derive(Debug, Default)]
+pub struct NodeTracer {
+    entity: Option<Entity>,
+    active: bool,
+    visits: Vec<NodeVisit>,
+    depth: u16,
+}
+
+impl NodeTracer {
+    pub fn new(entity: Option<Entity>) -> Self {
+        Self {
+            entity,
+            ..Self::default()
+        }
+    }
+
+    /// Start running the behavior of `entity`; returns whether it's traced.
+    pub fn begin(&mut self, entity: Entity) -> bool {
+        self.active = self.entity == Some(entity);
+        self.active
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Record entering a node; returns the visit to finish with `exit`.
+    pub fn enter(&mut self, name: Cow<'static, str>) -> usize {
+        self.visits.push(NodeVisit {
+            depth: self.depth,
+            name: name.into_owned(),
+            status: BehaviorStatus::Running,
+        });
+        self.depth += 1;
+        self.visits.len() - 1
+    }
+
+    pub fn exit(&mut self, visit: usize, status: BehaviorStatus) {
+        self.depth -= 1;
+        self.visits[visit].status = status;
+    }
+
+    /// Visits recorded since the last call.
+    pub fn take(&mut self) -> Vec<NodeVisit> {
+        std::mem::take(&mut self.visits)
+    }
+}
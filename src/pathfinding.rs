@@ -0,0 +1,167 @@
+use crate::map::Map;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// Integer costs keep the search exact and platform independent.
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+// Fixed expansion order; together with the (f, h, index) heap key this makes
+// equal-cost ties resolve the same way on every run.
+const NEIGHBORS: [(i32, i32, u32); 8] = [
+    (0, -1, STRAIGHT_COST),
+    (1, 0, STRAIGHT_COST),
+    (0, 1, STRAIGHT_COST),
+    (-1, 0, STRAIGHT_COST),
+    (1, -1, DIAGONAL_COST),
+    (1, 1, DIAGONAL_COST),
+    (-1, 1, DIAGONAL_COST),
+    (-1, -1, DIAGONAL_COST),
+];
+
+/// Octile distance, admissible because every step costs at least its base cost.
+fn heuristic(from: (u32, u32), to: (u32, u32)) -> u32 {
+    let dx = from.0.abs_diff(to.0);
+    let dy = from.1.abs_diff(to.1);
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+fn passable(map: &Map, x: i32, y: i32) -> bool {
+    map.in_bounds(x, y) && map.tile_at_pos(x as u32, y as u32).passable
+}
+
+/// A* over map tiles from `start` to `goal`, 8-connected without corner cutting.
+/// Entering a tile costs its base step cost times `1 + cost_mod`.
+/// Returns the tiles to walk through, excluding `start` and ending with `goal`,
+/// or `None` if the goal is out of bounds, impassable or unreachable.
+/// The start tile itself may be impassable so agents can always walk out.
+pub fn find_path(map: &Map, start: (u32, u32), goal: (u32, u32)) -> Option<Vec<(u32, u32)>> {
+    if !map.in_bounds(start.0 as i32, start.1 as i32)
+        || !passable(map, goal.0 as i32, goal.1 as i32)
+    {
+        return None;
+    }
+
+    let start_i = map.idx_xy(start.0, start.1);
+    let goal_i = map.idx_xy(goal.0, goal.1);
+
+    let mut g_score = vec![u32::MAX; map.len()];
+    let mut came_from = vec![usize::MAX; map.len()];
+    let mut closed = vec![false; map.len()];
+    let mut open = BinaryHeap::new();
+
+    g_score[start_i] = 0;
+    let h = heuristic(start, goal);
+    open.push(Reverse((h, h, start_i)));
+
+    while let Some(Reverse((_, _, i))) = open.pop() {
+        if closed[i] {
+            continue;
+        }
+        closed[i] = true;
+
+        if i == goal_i {
+            let mut path = Vec::new();
+            let mut cur = goal_i;
+            while cur != start_i {
+                path.push(map.xy_idx(cur));
+                cur = came_from[cur];
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let (x, y) = map.xy_idx(i);
+        for (dx, dy, base_cost) in NEIGHBORS {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if !passable(map, nx, ny) {
+                continue;
+            }
+            // no squeezing diagonally between two blocked tiles
+            let diagonal = dx != 0 && dy != 0;
+            if diagonal && !(passable(map, nx, y as i32) && passable(map, x as i32, ny)) {
+                continue;
+            }
+
+            let n = map.idx_xy(nx as u32, ny as u32);
+            if closed[n] {
+                continue;
+            }
+            let step = base_cost * (1 + map.tile_at_index(n).cost_mod as u32);
+            let tentative = g_score[i] + step;
+            if tentative < g_score[n] {
+                g_score[n] = tentative;
+                came_from[n] = i;
+                let h = heuristic((nx as u32, ny as u32), goal);
+                open.push(Reverse((tentative + h, h, n)));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_with_walls(width: u32, height: u32, walls: &[(u32, u32)]) -> Map {
+        let mut map = Map::new(width, height);
+        for &(x, y) in walls {
+            let i = map.idx_xy(x, y);
+            map.tile_at_index_mut(i).passable = false;
+        }
+        map
+    }
+
+    #[test]
+    fn straight_path_excludes_start_and_ends_on_goal() {
+        let map = Map::new(5, 5);
+        let path = find_path(&map, (0, 2), (3, 2)).unwrap();
+        assert_eq!(path, vec![(1, 2), (2, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn walled_off_goal_is_unreachable() {
+        let map = map_with_walls(5, 5, &[(2, 0), (2, 1), (2, 2), (2, 3), (2, 4)]);
+        assert_eq!(find_path(&map, (0, 0), (4, 4)), None);
+    }
+
+    #[test]
+    fn impassable_goal_has_no_path() {
+        let map = map_with_walls(3, 3, &[(2, 2)]);
+        assert_eq!(find_path(&map, (0, 0), (2, 2)), None);
+    }
+
+    #[test]
+    fn diagonals_do_not_cut_corners() {
+        // one blocked neighbor is enough to rule out the diagonal step
+        let map = map_with_walls(3, 3, &[(1, 0)]);
+        assert_eq!(find_path(&map, (0, 0), (1, 1)), Some(vec![(0, 1), (1, 1)]));
+
+        // and with both blocked there's no way through at all
+        let map = map_with_walls(3, 3, &[(1, 0), (0, 1)]);
+        assert_eq!(find_path(&map, (0, 0), (1, 1)), None);
+    }
+
+    #[test]
+    fn cost_mod_steers_around_slow_tiles() {
+        let mut map = Map::new(3, 3);
+        let i = map.idx_xy(1, 1);
+        map.tile_at_index_mut(i).cost_mod = 5;
+        let path = find_path(&map, (0, 1), (2, 1)).unwrap();
+        assert!(!path.contains(&(1, 1)), "{path:?}");
+    }
+
+    #[test]
+    fn equal_cost_ties_resolve_the_same_way() {
+        let map = Map::new(8, 8);
+        let path = find_path(&map, (0, 0), (5, 2)).unwrap();
+        for _ in 0..10 {
+            assert_eq!(find_path(&map, (0, 0), (5, 2)).unwrap(), path);
+        }
+        // the lower heuristic wins ties, so the diagonals come first
+        assert_eq!(path, vec![(1, 1), (2, 2), (3, 2), (4, 2), (5, 2)]);
+    }
+}
@@ -1,21 +1,36 @@
 use super::types::{Recipe, RecipeIndex};
+use super::validate::validate;
+use crate::items::ItemDb;
 use anyhow::{Context, Result};
-use std::{fs, path::Path};
+use std::{fs, path::Path, path::PathBuf};
 
-pub fn load_dir(dir: &Path) -> Result<RecipeIndex> {
-    let mut idx = RecipeIndex::default();
+/// Load every `.ron` recipe file in `dir` and validate the whole set against `items`.
+/// Validation problems come back as a `RecipeValidationError` inside the `anyhow::Error`.
+pub fn load_dir(dir: &Path, items: &ItemDb) -> Result<RecipeIndex> {
+    let mut paths = Vec::new();
     for entry in fs::read_dir(dir).with_context(|| format!("read_dir {:?}", dir))? {
-        let entry = entry?;
-        let path = entry.path();
+        let path = entry?.path();
         if path.extension().and_then(|s| s.to_str()) == Some("ron") {
-            let text =
-                fs::read_to_string(&path).with_context(|| format!("read_to_string {:?}", path))?;
-            let list: Vec<Recipe> =
-                ron::from_str(&text).with_context(|| format!("RON parse {:?}", path))?;
-            for r in list {
-                idx.insert(r);
-            }
+            paths.push(path);
         }
     }
+    // recipe ids and problem reports follow load order, keep it stable
+    paths.sort();
+
+    let mut loaded: Vec<(PathBuf, Recipe)> = Vec::new();
+    for path in paths {
+        let text =
+            fs::read_to_string(&path).with_context(|| format!("read_to_string {:?}", path))?;
+        let list: Vec<Recipe> =
+            ron::from_str(&text).with_context(|| format!("RON parse {:?}", path))?;
+        loaded.extend(list.into_iter().map(|r| (path.clone(), r)));
+    }
+
+    validate(&loaded, items)?;
+
+    let mut idx = RecipeIndex::default();
+    for (_, r) in loaded {
+        idx.insert(r);
+    }
     Ok(idx)
 }
@@ -1,14 +1,16 @@
 pub mod loader;
 pub mod types;
+pub mod validate;
 
+use crate::items::ItemDb;
 use std::path::Path;
 use types::RecipeIndex;
 
 pub struct RecipeDb(pub RecipeIndex);
 
 impl RecipeDb {
-    pub fn load_from_assets<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
-        let idx = loader::load_dir(dir.as_ref())?;
+    pub fn load_from_assets<P: AsRef<Path>>(dir: P, items: &ItemDb) -> anyhow::Result<Self> {
+        let idx = loader::load_dir(dir.as_ref(), items)?;
         Ok(Self(idx))
     }
     pub fn recipes_for(&self, product_id: &str) -> &[types::RecipeId] {
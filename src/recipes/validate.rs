@@ -0,0 +1,398 @@
+use super::types::{ProductKind, Recipe};
+use crate::items::ItemDb;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// One thing wrong with one recipe, located by file and recipe id.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RecipeProblem {
+    #[error("{}: recipe {recipe:?}: duplicate id, first defined in {}", .file.display(), .first_file.display())]
+    DuplicateId {
+        file: PathBuf,
+        recipe: String,
+        first_file: PathBuf,
+    },
+    #[error("{}: recipe {recipe:?}: unknown ingredient {id:?} ({kind:?})", .file.display())]
+    UnknownIngredient {
+        file: PathBuf,
+        recipe: String,
+        kind: ProductKind,
+        id: String,
+    },
+    #[error("{}: recipe {recipe:?}: zero qty for {id:?}", .file.display())]
+    ZeroQty {
+        file: PathBuf,
+        recipe: String,
+        id: String,
+    },
+    #[error("{}: recipe {recipe:?}: unknown tool {tool:?}", .file.display())]
+    UnknownTool {
+        file: PathBuf,
+        recipe: String,
+        tool: String,
+    },
+    #[error("{}: recipe {recipe:?}: cycle {}", .file.display(), .cycle.join(" -> "))]
+    Cycle {
+        file: PathBuf,
+        recipe: String,
+        cycle: Vec<String>,
+    },
+}
+
+/// Every problem found in a set of recipe files, in file and definition order.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{} problem(s) in recipe files:{}", .problems.len(), list(.problems))]
+pub struct RecipeValidationError {
+    pub problems: Vec<RecipeProblem>,
+}
+
+fn list(problems: &[RecipeProblem]) -> String {
+    let mut out = String::new();
+    for p in problems {
+        let _ = write!(out, "\n  {p}");
+    }
+    out
+}
+
+/// Check loaded recipes against each other and against the item registry.
+/// An ingredient or tool is known if it is an item definition or the product
+/// of some recipe; tools may also name an item tag (e.g. "hammer").
+pub fn validate(
+    recipes: &[(PathBuf, Recipe)],
+    items: &ItemDb,
+) -> Result<(), RecipeValidationError> {
+    let mut problems = Vec::new();
+
+    let products: HashSet<(ProductKind, &str)> = recipes
+        .iter()
+        .map(|(_, r)| (r.product.kind, r.product.id.as_str()))
+        .collect();
+    let is_known = |kind: ProductKind, id: &str| {
+        products.contains(&(kind, id)) || (kind == ProductKind::Item && items.def_id(id).is_some())
+    };
+
+    let mut first_seen: HashMap<&str, &PathBuf> = HashMap::new();
+    for (file, r) in recipes {
+        if let Some(first_file) = first_seen.get(r.id.as_str()) {
+            problems.push(RecipeProblem::DuplicateId {
+                file: file.clone(),
+                recipe: r.id.clone(),
+                first_file: (*first_file).clone(),
+            });
+        } else {
+            first_seen.insert(&r.id, file);
+        }
+
+        if r.product.qty == 0 {
+            problems.push(RecipeProblem::ZeroQty {
+                file: file.clone(),
+                recipe: r.id.clone(),
+                id: r.product.id.clone(),
+            });
+        }
+        for ingredient in &r.ingredients {
+            if ingredient.qty == 0 {
+                problems.push(RecipeProblem::ZeroQty {
+                    file: file.clone(),
+                    recipe: r.id.clone(),
+                    id: ingredient.id.clone(),
+                });
+            }
+            if !is_known(ingredient.kind, &ingredient.id) {
+                problems.push(RecipeProblem::UnknownIngredient {
+                    file: file.clone(),
+                    recipe: r.id.clone(),
+                    kind: ingredient.kind,
+                    id: ingredient.id.clone(),
+                });
+            }
+        }
+        for tool in &r.tools {
+            let known = is_known(ProductKind::Item, tool)
+                || items.0.iter().any(|(_, def)| def.has_tag(tool));
+            if !known {
+                problems.push(RecipeProblem::UnknownTool {
+                    file: file.clone(),
+                    recipe: r.id.clone(),
+                    tool: tool.clone(),
+                });
+            }
+        }
+    }
+
+    find_cycles(recipes, items, &mut problems);
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(RecipeValidationError { problems })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    New,
+    Active,
+    Done,
+}
+
+/// Depth-first walk over "product needs ingredient or tool" edges. A tool
+/// stands for every item it names, by id or tag, the way production planning
+/// resolves it. A cycle is reported once, at the recipe whose need closes it.
+fn find_cycles(recipes: &[(PathBuf, Recipe)], items: &ItemDb, problems: &mut Vec<RecipeProblem>) {
+    let mut by_product: HashMap<(ProductKind, &str), Vec<usize>> = HashMap::new();
+    for (i, (_, r)) in recipes.iter().enumerate() {
+        by_product
+            .entry((r.product.kind, &r.product.id))
+            .or_default()
+            .push(i);
+    }
+
+    // recipes each recipe needs the product of
+    let needs: Vec<Vec<usize>> = recipes
+        .iter()
+        .map(|(_, r)| {
+            let mut needed: Vec<(ProductKind, &str)> = r
+                .ingredients
+                .iter()
+                .map(|i| (i.kind, i.id.as_str()))
+                .collect();
+            for tool in &r.tools {
+                let mut named: Vec<&str> = items
+                    .0
+                    .iter()
+                    .filter(|(_, def)| def.id == *tool || def.has_tag(tool))
+                    .map(|(_, def)| def.id.as_str())
+                    .collect();
+                if named.is_empty() {
+                    named.push(tool);
+                }
+                needed.extend(named.into_iter().map(|id| (ProductKind::Item, id)));
+            }
+            needed
+                .iter()
+                .flat_map(|need| by_product.get(need).into_iter().flatten().copied())
+                .collect()
+        })
+        .collect();
+
+    let mut marks = vec![Mark::New; recipes.len()];
+    let mut stack = Vec::new();
+    for start in 0..recipes.len() {
+        visit(start, recipes, &needs, &mut marks, &mut stack, problems);
+    }
+}
+
+fn visit(
+    i: usize,
+    recipes: &[(PathBuf, Recipe)],
+    needs: &[Vec<usize>],
+    marks: &mut [Mark],
+    stack: &mut Vec<usize>,
+    problems: &mut Vec<RecipeProblem>,
+) {
+    if marks[i] != Mark::New {
+        return;
+    }
+    marks[i] = Mark::Active;
+    stack.push(i);
+
+    let (file, recipe) = &recipes[i];
+    for &next in &needs[i] {
+        if marks[next] == Mark::Active {
+            let from = stack.iter().position(|&s| s == next).unwrap_or(0);
+            let mut cycle: Vec<String> = stack[from..]
+                .iter()
+                .map(|&s| recipes[s].1.product.id.clone())
+                .collect();
+            cycle.push(recipes[next].1.product.id.clone());
+            problems.push(RecipeProblem::Cycle {
+                file: file.clone(),
+                recipe: recipe.id.clone(),
+                cycle,
+            });
+        } else {
+            visit(next, recipes, needs, marks, stack, problems);
+        }
+    }
+
+    stack.pop();
+    marks[i] = Mark::Done;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::types::{ItemDef, ItemIndex};
+
+    fn items() -> ItemDb {
+        let mut idx = ItemIndex::default();
+        for def in [
+            r#"(id: "log", name: "Log", color: (0, 0, 0, 255), shape: (1.0, 1.0))"#,
+            r#"(id: "mallet", name: "Mallet", color: (0, 0, 0, 255), shape: (1.0, 1.0), tags: ["hammer"])"#,
+        ] {
+            idx.insert(ron::from_str::<ItemDef>(def).unwrap());
+        }
+        ItemDb(idx)
+    }
+
+    // `recipes` as if loaded from a.ron, then b.ron
+    fn check(a: &str, b: &str) -> Vec<RecipeProblem> {
+        let mut recipes = Vec::new();
+        for (file, text) in [("a.ron", a), ("b.ron", b)] {
+            let list: Vec<Recipe> = ron::from_str(text).unwrap();
+            recipes.extend(list.into_iter().map(|r| (PathBuf::from(file), r)));
+        }
+        match validate(&recipes, &items()) {
+            Ok(()) => Vec::new(),
+            Err(e) => e.problems,
+        }
+    }
+
+    fn recipe(id: &str, product: &str, ingredients: &str, tools: &str) -> String {
+        format!(
+            r#"(id: "{id}", product: (kind: item, id: "{product}", qty: 1),
+                ingredients: [{ingredients}], tools: [{tools}], time_ms: 0)"#
+        )
+    }
+
+    fn file(recipes: &[String]) -> String {
+        format!("[{}]", recipes.join(","))
+    }
+
+    #[test]
+    fn valid_recipes_pass() {
+        let plank = recipe("plank", "plank", r#"(kind: item, id: "log", qty: 1)"#, "");
+        let table = recipe(
+            "table",
+            "table",
+            r#"(kind: item, id: "plank", qty: 4)"#,
+            r#""hammer""#,
+        );
+        assert_eq!(check(&file(&[plank]), &file(&[table])), []);
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let plank = recipe("plank", "plank", r#"(kind: item, id: "log", qty: 1)"#, "");
+        let plank = file(&[plank]);
+        let problems = check(&plank, &plank);
+        assert_eq!(
+            problems,
+            [RecipeProblem::DuplicateId {
+                file: PathBuf::from("b.ron"),
+                recipe: String::from("plank"),
+                first_file: PathBuf::from("a.ron"),
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_ingredient() {
+        // a building of that id doesn't make an item known either
+        let plank = recipe(
+            "plank",
+            "plank",
+            r#"(kind: building, id: "log", qty: 1)"#,
+            "",
+        );
+        assert_eq!(
+            check(&file(&[plank]), "[]"),
+            [RecipeProblem::UnknownIngredient {
+                file: PathBuf::from("a.ron"),
+                recipe: String::from("plank"),
+                kind: ProductKind::Building,
+                id: String::from("log"),
+            }]
+        );
+    }
+
+    #[test]
+    fn zero_qty() {
+        let plank = recipe("plank", "plank", r#"(kind: item, id: "log", qty: 0)"#, "");
+        assert_eq!(
+            check(&file(&[plank]), "[]"),
+            [RecipeProblem::ZeroQty {
+                file: PathBuf::from("a.ron"),
+                recipe: String::from("plank"),
+                id: String::from("log"),
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_tool() {
+        let plank = recipe(
+            "plank",
+            "plank",
+            r#"(kind: item, id: "log", qty: 1)"#,
+            r#""saw""#,
+        );
+        assert_eq!(
+            check(&file(&[plank]), "[]"),
+            [RecipeProblem::UnknownTool {
+                file: PathBuf::from("a.ron"),
+                recipe: String::from("plank"),
+                tool: String::from("saw"),
+            }]
+        );
+    }
+
+    #[test]
+    fn ingredient_cycle() {
+        let a = recipe("a", "plank", r#"(kind: item, id: "beam", qty: 1)"#, "");
+        let b = recipe("b", "beam", r#"(kind: item, id: "plank", qty: 1)"#, "");
+        assert_eq!(
+            check(&file(&[a]), &file(&[b])),
+            [RecipeProblem::Cycle {
+                file: PathBuf::from("b.ron"),
+                recipe: String::from("b"),
+                cycle: vec![
+                    String::from("plank"),
+                    String::from("beam"),
+                    String::from("plank")
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn tool_cycle() {
+        // the mallet (a "hammer") needs planks, which need a hammer
+        let plank = recipe(
+            "plank",
+            "plank",
+            r#"(kind: item, id: "log", qty: 1)"#,
+            r#""hammer""#,
+        );
+        let mallet = recipe(
+            "mallet",
+            "mallet",
+            r#"(kind: item, id: "plank", qty: 2)"#,
+            "",
+        );
+        assert_eq!(
+            check(&file(&[plank]), &file(&[mallet])),
+            [RecipeProblem::Cycle {
+                file: PathBuf::from("b.ron"),
+                recipe: String::from("mallet"),
+                cycle: vec![
+                    String::from("plank"),
+                    String::from("mallet"),
+                    String::from("plank")
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn products_of_another_kind_are_no_cycle() {
+        // the building "plank" isn't the item the recipe needs
+        let plank = recipe("plank", "plank", r#"(kind: item, id: "log", qty: 1)"#, "");
+        let shed = r#"(id: "shed", product: (kind: building, id: "log", qty: 1),
+            ingredients: [(kind: item, id: "plank", qty: 1)], tools: [], time_ms: 0)"#;
+        assert_eq!(check(&file(&[plank]), &format!("[{shed}]")), []);
+    }
+}
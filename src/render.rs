@@ -0,0 +1,122 @@
+use crate::window::Window;
+use anvil::btree::BehaviorStatus;
+use anvil::components::{Item, Position, Shape};
+use anvil::items::ItemDb;
+use anvil::map::Map;
+use anvil::{Knowledge, Properties};
+use hecs::{Entity, World as ComponentRegistry};
+use std::collections::HashMap;
+
+pub fn render_frame(
+    window: &mut Window,
+    properties: &Properties,
+    map: &Map,
+    items: &ItemDb,
+    knowledges: &HashMap<Entity, Knowledge>,
+    registry: &mut ComponentRegistry,
+) {
+    window.start_frame();
+
+    render_map(window, properties, map);
+    render_entites(window, properties, items, registry);
+    render_node_trace(window, properties, knowledges);
+
+    window.present_frame();
+}
+
+fn render_map(window: &mut Window, properties: &Properties, map: &Map) {
+    for map_y in 0..map.height {
+        for map_x in 0..map.width {
+            let tile = map.tile_at_pos(map_x, map_y);
+            window.draw_map_tile((map_x, map_y), tile.visual.shape_id);
+        }
+    }
+
+    if properties.draw_map_grid {
+        for x in 0..=map.width {
+            // vertical lines
+            window.draw_line(
+                (x as f32, 0.),
+                (x as f32, map.height as f32),
+                (0, 0, 0, 255),
+            );
+        }
+
+        for y in 0..=map.height {
+            // horizontal lines
+            window.draw_line((0., y as f32), (map.width as f32, y as f32), (0, 0, 0, 255));
+        }
+    }
+}
+
+fn render_entites(
+    window: &mut Window,
+    properties: &Properties,
+    items: &ItemDb,
+    registry: &mut ComponentRegistry,
+) {
+    for (id, (pos, shape)) in registry.query_mut::<(&Position, &Shape)>() {
+        draw_entity(
+            window,
+            properties,
+            id,
+            pos,
+            (shape.width, shape.height),
+            shape.color,
+        );
+    }
+
+    // items take their look from the item definition
+    for (id, (pos, item)) in registry.query_mut::<(&Position, &Item)>() {
+        let def = items.get(item.def_id);
+        draw_entity(window, properties, id, pos, def.shape, def.color);
+    }
+}
+
+fn draw_entity(
+    window: &mut Window,
+    properties: &Properties,
+    id: Entity,
+    pos: &Position,
+    (width, height): (f32, f32),
+    color: (u8, u8, u8, u8),
+) {
+    window.draw_rect(pos.x - width / 2., pos.y - width / 2., width, height, color);
+    window.draw_dot(pos.x, pos.y, (255, 255, 255, 255));
+
+    // draw selection marker if entity is selected
+    if properties.selected_entity == Some(id) {
+        window.draw_selection_marker(pos.x, pos.y);
+    }
+}
+
+/// Behavior tree nodes the selected entity visited last tick, as an indented
+/// tree colored by the status each node returned.
+fn render_node_trace(
+    window: &mut Window,
+    properties: &Properties,
+    knowledges: &HashMap<Entity, Knowledge>,
+) {
+    let Some(trace) = properties
+        .selected_entity
+        .and_then(|e| knowledges.get(&e))
+        .and_then(|k| k.node_trace.latest())
+    else {
+        return;
+    };
+
+    let mut lines = vec![(
+        format!("tick {} {}", trace.tick, trace.behavior),
+        (255, 255, 255, 255),
+    )];
+    for visit in &trace.visits {
+        let color = match visit.status {
+            BehaviorStatus::Success => (120, 220, 120, 255),
+            BehaviorStatus::Failure => (240, 110, 110, 255),
+            BehaviorStatus::Running => (240, 210, 100, 255),
+        };
+        let indent = "  ".repeat(visit.depth as usize + 1);
+        lines.push((format!("{indent}{} {:?}", visit.name, visit.status), color));
+    }
+    window.draw_text_panel((10, 10), &lines);
+}
@@ -1,11 +1,10 @@
-use crate::time::{FixedDt, Tick};
+use crate::time::FixedDt;
 use std::time::Instant;
 
 pub struct SimLoop {
     pub fixed: FixedDt,
     accumulator: f32,
     last_real: Instant,
-    pub tick: Tick,
     pub max_steps_per_frame: u32, // back-pressure guard
 }
 
@@ -15,7 +14,6 @@ impl SimLoop {
             fixed: FixedDt::from_hz(hz),
             accumulator: 0.0,
             last_real: Instant::now(),
-            tick: Tick(0),
             max_steps_per_frame: 8,
         }
     }
@@ -38,11 +36,6 @@ impl SimLoop {
         steps
     }
 
-    /// Advance simulation tick counter once per fixed step.
-    pub fn advance_tick(&mut self) {
-        self.tick = Tick(self.tick.0 + 1);
-    }
-
     /// Alpha in [0,1) for render interpolation if you need it.
     pub fn alpha(&self) -> f32 {
         (self.accumulator / self.fixed.seconds).clamp(0.0, 1.0)
@@ -1,37 +1,84 @@
+use crate::behavior_trees::BehaviorTreeDb;
 use crate::btree::BehaviorStatus::Running;
-use crate::btree::{BehaviorStatus, BehaviorTreeNode};
+use crate::btree::{BehaviorContext, BehaviorStatus};
 use crate::components::StateType::Move;
-use crate::components::{Hunger, Movement, Position, Shape, State};
+use crate::components::{Hunger, Movement, Path, Position, State};
 use crate::entity_commands::EntityCommand;
-use crate::map::Map;
-use crate::window::Window;
-use crate::{behaviors, BehaviorList, Knowledge, Properties};
+use crate::node_trace::TickTrace;
+use crate::time::Tick;
+use crate::utility::{ScoreContext, UtilityDb};
+use crate::{behaviors, BehaviorList, Knowledge, ScheduledBehavior, PRIORITY_IDLE};
 use hecs::Entity;
 use hecs::World as ComponentRegistry;
 use std::collections::HashMap;
 
-pub fn choose_behaviors(
+// Ticks before a goal whose behavior failed (e.g. no food left) is tried again.
+const RETRY_TICKS: u64 = 60 * 10;
+
+// Goals are re-scored every this many ticks, not every tick.
+const DECISION_INTERVAL: u64 = 30;
+
+/// Score every agent's goals and schedule the ones that score high enough on
+/// top of whatever the agent is doing. A goal is only scheduled once at a time,
+/// not again right after it failed, and `once` goals never again.
+pub fn choose_goals(
     behaviors: &mut HashMap<Entity, BehaviorList>,
     knowledges: &mut HashMap<Entity, Knowledge>,
-    entity_commands: &mut Vec<EntityCommand>,
-    registry: &mut ComponentRegistry,
+    goals: &UtilityDb,
+    trees: &BehaviorTreeDb,
+    ctx: &ScoreContext,
 ) {
-    // react to hunger, choose behavior
-    for (entity, (hunger)) in registry.query_mut::<(&Hunger)>() {
-        let mut behavior = behaviors::do_nothing();
-        if hunger.value > 3 {
-            behavior = behaviors::find_food();
-            println!("Behavior updated! Hungry!")
-        }
-        behaviors.insert(entity, vec![behavior]);
+    if !ctx.tick.0.is_multiple_of(DECISION_INTERVAL) {
+        return;
+    }
+    let mut keys: Vec<Entity> = behaviors.keys().cloned().collect();
+    keys.sort_unstable_by_key(|e| e.to_bits().get());
+
+    for e in keys {
+        let (Some(bhvs), Some(knowledge)) = (behaviors.get_mut(&e), knowledges.get_mut(&e)) else {
+            continue;
+        };
+        let ranked = goals.rank(knowledge, ctx);
+        for scored in &ranked {
+            let Some(goal) = goals.goal(&scored.goal) else {
+                continue;
+            };
+            if scored.score < goal.min_score {
+                continue;
+            }
+            let waiting = knowledge
+                .retry_at
+                .get(&goal.id)
+                .is_some_and(|at| ctx.tick < *at);
+            if waiting || bhvs.iter().any(|b| b.name == goal.id) {
+                continue;
+            }
+            let Some(node) = trees.build(&goal.tree) else {
+                continue;
+            };
+            if goal.once {
+                knowledge.retry_at.insert(goal.id.clone(), Tick(u64::MAX));
+            }
+            bhvs.push(ScheduledBehavior::new(&goal.id, goal.priority, node));
+        }
+        knowledge.goal_scores = ranked;
     }
 }
 
+/// Run the highest priority behavior of every agent. If that isn't the one
+/// that was running last tick, the old one is aborted first; it stays in the
+/// list and starts over once it is the highest priority again.
+///
+/// Preempted behaviors restart rather than resume, since what they were doing
+/// may be stale by then (a target item eaten, a path blocked). Progress worth
+/// keeping lives in the agent's `Knowledge` instead, e.g. the inventory and the
+/// build site, so a restarted tree skips what's already done.
 pub fn run_behaviors(
     behaviors: &mut HashMap<Entity, BehaviorList>,
     knowledges: &mut HashMap<Entity, Knowledge>,
     entity_commands: &mut Vec<EntityCommand>,
     registry: &mut ComponentRegistry,
+    ctx: &mut BehaviorContext,
 ) {
     let mut keys: Vec<Entity> = behaviors.keys().cloned().collect();
     keys.sort_unstable_by_key(|e| e.to_bits().get());
@@ -43,41 +90,96 @@ pub fn run_behaviors(
         // if behaviors is empty, pring message and assign do_nothing()
         if bhvs.is_empty() {
             println!("All behaviors completed, assigning DoNothing");
-            bhvs.push(behaviors::do_nothing())
+            bhvs.push(ScheduledBehavior::new(
+                "do_nothing",
+                PRIORITY_IDLE,
+                behaviors::do_nothing(),
+            ))
         }
+
+        // highest priority wins, the earliest scheduled among equals
+        let mut top = 0;
+        for (i, b) in bhvs.iter().enumerate() {
+            if b.priority > bhvs[top].priority {
+                top = i;
+            }
+        }
+        if let Some(prev) = bhvs.iter().position(|b| b.running) {
+            if prev != top {
+                bhvs[prev].node.on_abort(knldg, registry, ctx);
+                bhvs[prev].running = false;
+            }
+        }
+
         // when returned status is not running, remove finished behavior
-        let status = bhvs[0].run(knldg, entity_commands, registry);
+        let traced = ctx.tracer.begin(e);
+        let status = bhvs[top].node.tick(knldg, entity_commands, registry, ctx);
+        if traced {
+            knldg.node_trace.push(TickTrace {
+                tick: ctx.tick.0,
+                entity: e,
+                behavior: bhvs[top].name.clone(),
+                visits: ctx.tracer.take(),
+            });
+        }
+        bhvs[top].running = status == Running;
         match status {
             BehaviorStatus::Success => {
-                bhvs.remove(0);
+                bhvs.remove(top);
             }
             BehaviorStatus::Failure => {
-                bhvs.remove(0);
+                // only ever push the retry later, `once` goals wait forever
+                let failed = bhvs.remove(top);
+                let retry = Tick(ctx.tick.0 + RETRY_TICKS);
+                let at = knldg.retry_at.entry(failed.name).or_insert(retry);
+                *at = (*at).max(retry);
             }
             _ => {}
         }
     }
 }
 
+const MOVE_SPEED: f32 = 0.07;
+
 pub fn movement(registry: &mut ComponentRegistry) {
-    for (_, (pos, movement, state)) in
-        registry.query_mut::<(&mut Position, &mut Movement, &State)>()
+    for (_, (pos, movement, state, path)) in
+        registry.query_mut::<(&mut Position, &Movement, &State, Option<&mut Path>)>()
     {
         if state.state != Move {
             continue;
         }
 
+        // follow path waypoints when there is a path, otherwise head straight for the destination
+        let (target_x, target_y) = match &path {
+            Some(path) => match path.current() {
+                Some(waypoint) => waypoint,
+                None => continue, // path finished
+            },
+            None => (movement.destination_x, movement.destination_y),
+        };
+
         // get distance to destination
-        let dist_x = movement.destination_x - pos.x;
-        let dist_y = movement.destination_y - pos.y;
+        let dist_x = target_x - pos.x;
+        let dist_y = target_y - pos.y;
+        let dist = dist_x.hypot(dist_y);
+
+        // snap onto reachable waypoints so paths end exactly where they were planned
+        if let Some(path) = path {
+            if dist <= MOVE_SPEED {
+                pos.x = target_x;
+                pos.y = target_y;
+                path.next += 1;
+                continue;
+            }
+        }
 
         // normalise direction
-        let direction_x = dist_x / dist_x.hypot(dist_y);
-        let direction_y = dist_y / dist_x.hypot(dist_y);
+        let direction_x = dist_x / dist;
+        let direction_y = dist_y / dist;
 
         // modify position
-        pos.x += direction_x * 0.07;
-        pos.y += direction_y * 0.07;
+        pos.x += direction_x * MOVE_SPEED;
+        pos.y += direction_y * MOVE_SPEED;
     }
 }
 
@@ -93,65 +195,229 @@ pub fn hunger(dt_seconds: f32, registry: &mut ComponentRegistry) {
     }
 }
 
-pub fn render_frame(
-    window: &mut Window,
-    properties: &Properties,
-    map: &Map,
-    registry: &mut ComponentRegistry,
-) {
-    window.start_frame();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::BehaviorTreeNode;
+    use crate::items::{types::ItemIndex, ItemDb};
+    use crate::map::Map;
+    use crate::node_trace::NodeTracer;
+    use crate::recipes::{types::RecipeIndex, RecipeDb};
+    use crate::PRIORITY_NEED;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
-    render_map(window, properties, map);
-    render_entites(window, properties, registry);
+    type Log = Rc<RefCell<Vec<String>>>;
 
-    window.present_frame();
-}
+    // Runs `steps` times and then ends with `last`, logging every run and abort.
+    struct Steps {
+        name: &'static str,
+        steps: u32,
+        last: BehaviorStatus,
+        done: u32,
+        log: Log,
+    }
+
+    impl BehaviorTreeNode for Steps {
+        fn run(
+            &mut self,
+            _: &mut Knowledge,
+            _: &mut Vec<EntityCommand>,
+            _: &mut ComponentRegistry,
+            _: &mut BehaviorContext,
+        ) -> BehaviorStatus {
+            self.done += 1;
+            self.log
+                .borrow_mut()
+                .push(format!("{} {}", self.name, self.done));
+            if self.done == self.steps {
+                self.done = 0;
+                self.last
+            } else {
+                Running
+            }
+        }
+
+        fn reset(&mut self) {
+            self.done = 0;
+        }
 
-fn render_map(window: &mut Window, properties: &Properties, map: &Map) {
-    for map_y in 0..map.height {
-        for map_x in 0..map.width {
-            let tile = map.tile_at_pos(map_x, map_y);
-            // window.draw_map_tile((map_x, map_y), tile.visual.shape_id);
-            window.draw_rect(map_x as f32, map_y as f32, 1., 1., (100, 100, 100, 100))
+        fn on_abort(
+            &mut self,
+            _: &mut Knowledge,
+            _: &mut ComponentRegistry,
+            _: &mut BehaviorContext,
+        ) {
+            self.log.borrow_mut().push(format!("{} aborted", self.name));
+            self.reset();
         }
     }
 
-    if properties.draw_map_grid {
-        for x in 0..=map.width {
-            // vertical lines
-            window.draw_line(
-                (x as f32, 0.),
-                (x as f32, map.height as f32),
-                (0, 0, 0, 255),
+    fn steps(name: &'static str, steps: u32, last: BehaviorStatus, log: &Log) -> Box<Steps> {
+        Box::new(Steps {
+            name,
+            steps,
+            last,
+            done: 0,
+            log: log.clone(),
+        })
+    }
+
+    // One agent and an empty world to run its behaviors in.
+    struct Agent {
+        entity: Entity,
+        registry: ComponentRegistry,
+        behaviors: HashMap<Entity, BehaviorList>,
+        knowledges: HashMap<Entity, Knowledge>,
+        map: Map,
+        items: ItemDb,
+        recipes: RecipeDb,
+        trees: BehaviorTreeDb,
+    }
+
+    impl Agent {
+        fn new() -> Self {
+            let mut registry = ComponentRegistry::new();
+            let entity = registry.spawn(());
+            Self {
+                entity,
+                registry,
+                behaviors: HashMap::from([(entity, BehaviorList::new())]),
+                knowledges: HashMap::from([(entity, Knowledge::new(entity))]),
+                map: Map::new(4, 4),
+                items: ItemDb(ItemIndex::default()),
+                recipes: RecipeDb(RecipeIndex::default()),
+                trees: BehaviorTreeDb::load_from_assets(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/assets/behaviors"
+                ))
+                .unwrap(),
+            }
+        }
+
+        fn schedule(&mut self, behavior: ScheduledBehavior) {
+            self.behaviors.get_mut(&self.entity).unwrap().push(behavior);
+        }
+
+        fn run(&mut self, tick: u64) {
+            run_behaviors(
+                &mut self.behaviors,
+                &mut self.knowledges,
+                &mut Vec::new(),
+                &mut self.registry,
+                &mut BehaviorContext {
+                    map: &mut self.map,
+                    items: &self.items,
+                    recipes: &self.recipes,
+                    trees: &self.trees,
+                    dt_seconds: 1. / 60.,
+                    tick: Tick(tick),
+                    tracer: NodeTracer::default(),
+                },
             );
         }
 
-        for y in 0..=map.height {
-            // horizontal lines
-            window.draw_line((0., y as f32), (map.width as f32, y as f32), (0, 0, 0, 255));
+        fn names(&self) -> Vec<&str> {
+            self.behaviors[&self.entity]
+                .iter()
+                .map(|b| b.name.as_str())
+                .collect()
         }
     }
-}
 
-fn render_entites(window: &mut Window, properties: &Properties, registry: &mut ComponentRegistry) {
-    for (id, (pos, shape)) in registry.query_mut::<(&Position, &Shape)>() {
-        window.draw_rect(
-            pos.x - shape.width / 2.,
-            pos.y - shape.width / 2.,
-            shape.width,
-            shape.height,
-            shape.color,
-        );
-        window.draw_dot(pos.x, pos.y, (255, 255, 255, 255));
-
-        // draw selection marker if entity is selected
-        match properties.selected_entity {
-            None => {}
-            Some(selected_entity) => {
-                if selected_entity == id {
-                    window.draw_selection_marker(pos.x, pos.y);
-                }
+    // Restarting is the intended resume: the abort releases what the tree held
+    // (a reserved site, a path), so carrying on mid-tree would act on state
+    // that's gone. What the agent got done lives in its `Knowledge`.
+    #[test]
+    fn preempted_behavior_is_aborted_and_starts_over() {
+        let mut agent = Agent::new();
+        let log = Log::default();
+        let work = steps("work", 3, BehaviorStatus::Success, &log);
+        agent.schedule(ScheduledBehavior::new("work", PRIORITY_IDLE + 1, work));
+        for tick in 0..6 {
+            if tick == 2 {
+                let need = steps("need", 2, BehaviorStatus::Success, &log);
+                agent.schedule(ScheduledBehavior::new("need", PRIORITY_NEED, need));
             }
+            agent.run(tick);
         }
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                "work 1",
+                "work 2",
+                "work aborted",
+                "need 1",
+                "need 2",
+                "work 1",
+                "work 2",
+            ]
+        );
+        // finished behaviors leave the list, the preempted one stays
+        assert_eq!(agent.names(), ["work"]);
+    }
+
+    #[test]
+    fn goals_below_min_score_are_not_scheduled() {
+        use crate::utility::types::{Consideration, Curve, GoalDef, Input};
+
+        // both score 0.5 whatever the agent's state
+        let goal = |id: &str, min_score| GoalDef {
+            id: id.to_string(),
+            tree: String::from("eat"),
+            priority: PRIORITY_NEED,
+            min_score,
+            once: false,
+            considerations: vec![Consideration {
+                input: Input::TimeOfDay,
+                range: (0., 1.),
+                curve: Curve::Linear {
+                    slope: 0.,
+                    intercept: 0.5,
+                },
+            }],
+        };
+        let goals = UtilityDb {
+            goals: vec![goal("picky", 0.6), goal("keen", 0.5)],
+        };
+        let mut agent = Agent::new();
+        choose_goals(
+            &mut agent.behaviors,
+            &mut agent.knowledges,
+            &goals,
+            &agent.trees,
+            &ScoreContext {
+                registry: &agent.registry,
+                items: &agent.items,
+                tick: Tick(0),
+                fixed: crate::time::FixedDt::from_hz(60),
+            },
+        );
+
+        assert_eq!(agent.names(), ["keen"]);
+        // the ranking keeps both for inspection
+        let scores = &agent.knowledges[&agent.entity].goal_scores;
+        assert_eq!(scores.len(), 2);
+    }
+
+    #[test]
+    fn failure_delays_retry_but_keeps_once_goals_retired() {
+        let mut agent = Agent::new();
+        let log = Log::default();
+        let knowledge = agent.knowledges.get_mut(&agent.entity).unwrap();
+        knowledge
+            .retry_at
+            .insert(String::from("once"), Tick(u64::MAX));
+        let once = steps("once", 1, BehaviorStatus::Failure, &log);
+        let again = steps("again", 1, BehaviorStatus::Failure, &log);
+        agent.schedule(ScheduledBehavior::new("once", PRIORITY_NEED, once));
+        agent.schedule(ScheduledBehavior::new("again", PRIORITY_NEED, again));
+        agent.run(100);
+        agent.run(101);
+
+        let retry_at = &agent.knowledges[&agent.entity].retry_at;
+        assert_eq!(retry_at["once"], Tick(u64::MAX));
+        assert_eq!(retry_at["again"], Tick(101 + RETRY_TICKS));
     }
 }
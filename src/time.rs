@@ -13,3 +13,13 @@ impl FixedDt {
         }
     }
 }
+
+/// Length of one in-game day.
+pub const DAY_LENGTH_SECONDS: u32 = 10 * 60;
+
+/// Fraction of the current day that has passed at `tick`, in [0, 1).
+/// Day one starts at tick 0, at dawn.
+pub fn time_of_day(tick: Tick, fixed: FixedDt) -> f32 {
+    let ticks_per_day = ((DAY_LENGTH_SECONDS as f32 / fixed.seconds).round() as u64).max(1);
+    (tick.0 % ticks_per_day) as f32 / ticks_per_day as f32
+}
@@ -0,0 +1,328 @@
+use anyhow::{bail, Context, Result};
+use bincode::config::{standard, Configuration};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{ErrorKind, Read, Write};
+
+// File layout, all integers little endian:
+//
+//   MAGIC | version: u32
+//   record*: kind: u8 | len: u32 | payload: [u8; len]   (payload is bincode)
+//
+// The first record is `META`, the run ends with `END` (or `CRASH`) and then
+// `CHECKSUM`, whose payload is the blake3 hash of every byte before it.
+//
+// Version 1 was a bare bincode stream without this header, see `legacy`.
+
+pub const MAGIC: &[u8; 8] = b"ANVILTRC";
+
+/// Format written by this build, the only framed one so far.
+pub const FORMAT_VERSION: u32 = 2;
+
+pub const META: u8 = 1;
+pub const TICK: u8 = 2;
+pub const CHECKPOINT: u8 = 3;
+pub const END: u8 = 4;
+pub const CRASH: u8 = 5;
+pub const CHECKSUM: u8 = 0xff;
+
+// Larger records are taken as corruption rather than allocated.
+const MAX_RECORD_LEN: u32 = 64 * 1024 * 1024;
+
+pub fn kind_name(kind: u8) -> &'static str {
+    match kind {
+        META => "meta",
+        TICK => "tick",
+        CHECKPOINT => "checkpoint",
+        END => "end",
+        CRASH => "crash",
+        CHECKSUM => "checksum",
+        _ => "unknown",
+    }
+}
+
+fn config() -> Configuration {
+    standard()
+}
+
+/// Writes the header and framed records, hashing everything it writes.
+pub struct FrameWriter<W: Write> {
+    w: W,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(mut w: W) -> Result<Self> {
+        let mut hasher = blake3::Hasher::new();
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        w.write_all(&header)?;
+        hasher.update(&header);
+        Ok(Self { w, hasher })
+    }
+
+    pub fn record<T: Serialize>(&mut self, kind: u8, value: &T) -> Result<()> {
+        let payload = bincode::serde::encode_to_vec(value, config())?;
+        let mut frame = Vec::with_capacity(payload.len() + 5);
+        frame.push(kind);
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        self.w.write_all(&frame)?;
+        self.hasher.update(&frame);
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+
+    /// Write the checksum record and flush; nothing may follow.
+    pub fn finish(mut self) -> Result<W> {
+        let hash = self.hasher.finalize();
+        self.w.write_all(&[CHECKSUM])?;
+        self.w.write_all(&32u32.to_le_bytes())?;
+        self.w.write_all(hash.as_bytes())?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// A record as read from the file, payload still encoded.
+pub struct Frame {
+    pub kind: u8,
+    /// Byte offset of the record in the file, for error messages.
+    pub offset: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T> {
+        let (value, used) = bincode::serde::decode_from_slice(&self.payload, config())
+            .with_context(|| {
+                format!(
+                    "bad {} record at byte {}",
+                    kind_name(self.kind),
+                    self.offset
+                )
+            })?;
+        if used != self.payload.len() {
+            bail!(
+                "bad {} record at byte {}: {} trailing bytes",
+                kind_name(self.kind),
+                self.offset,
+                self.payload.len() - used
+            );
+        }
+        Ok(value)
+    }
+}
+
+/// Reads framed records after the header, checking the checksum when it
+/// gets to it.
+pub struct FrameReader<R: Read> {
+    r: R,
+    hasher: blake3::Hasher,
+    offset: u64,
+    /// The checksum record was read and matched.
+    pub verified: bool,
+    /// End the file at a record that was cut short instead of failing.
+    pub recover: bool,
+    /// Where the file was cut short (inside a record or before the checksum),
+    /// once `recover` ran into it.
+    pub cut_at: Option<u64>,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Reader for a stream whose header (`MAGIC` and version) was already read.
+    pub fn after_header(r: R, version: u32) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(MAGIC);
+        hasher.update(&version.to_le_bytes());
+        Self {
+            r,
+            hasher,
+            offset: (MAGIC.len() + 4) as u64,
+            verified: false,
+            recover: false,
+            cut_at: None,
+        }
+    }
+
+    /// The next record, or `None` after the checksum. A file that ends
+    /// before it, inside a record or not, is an error unless recovering.
+    pub fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let offset = self.offset;
+        let mut kind = [0u8; 1];
+        match self.r.read_exact(&mut kind) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                if !self.verified {
+                    if !self.recover {
+                        bail!("file ends before the checksum, it was cut short");
+                    }
+                    self.cut_at = Some(offset);
+                }
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let kind = kind[0];
+        if self.verified {
+            bail!("record after the checksum at byte {offset}");
+        }
+
+        let mut len = [0u8; 4];
+        if !self.read_exact(&mut len)? {
+            return self.cut_short(kind, offset);
+        }
+        let len = u32::from_le_bytes(len);
+        if len > MAX_RECORD_LEN {
+            bail!(
+                "{} record at byte {offset} claims {len} bytes, the file is corrupt",
+                kind_name(kind)
+            );
+        }
+        let mut payload = vec![0u8; len as usize];
+        if !self.read_exact(&mut payload)? {
+            return self.cut_short(kind, offset);
+        }
+        self.offset += 5 + len as u64;
+
+        if kind == CHECKSUM {
+            let actual = self.hasher.finalize();
+            if payload != actual.as_bytes() {
+                bail!("checksum mismatch at byte {offset}, the file is corrupt");
+            }
+            self.verified = true;
+            return self.read_frame();
+        }
+        self.hasher.update(&[kind]);
+        self.hasher.update(&len.to_le_bytes());
+        self.hasher.update(&payload);
+        Ok(Some(Frame {
+            kind,
+            offset,
+            payload,
+        }))
+    }
+
+    // false if the file ended first
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<bool> {
+        match self.r.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn cut_short(&mut self, kind: u8, offset: u64) -> Result<Option<Frame>> {
+        if !self.recover {
+            bail!(
+                "file ends inside the {} record at byte {offset}, it was cut short",
+                kind_name(kind)
+            );
+        }
+        self.cut_at = Some(offset);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER_LEN: usize = MAGIC.len() + 4;
+
+    // a file with a meta and a tick record, checksummed
+    fn file() -> Vec<u8> {
+        let mut w = FrameWriter::new(Vec::new()).unwrap();
+        w.record(META, &60u32).unwrap();
+        w.record(TICK, &"tick three".to_string()).unwrap();
+        w.finish().unwrap()
+    }
+
+    fn reader(bytes: &[u8]) -> FrameReader<&[u8]> {
+        assert_eq!(&bytes[..MAGIC.len()], MAGIC);
+        let version = u32::from_le_bytes(bytes[MAGIC.len()..HEADER_LEN].try_into().unwrap());
+        assert_eq!(version, FORMAT_VERSION);
+        FrameReader::after_header(&bytes[HEADER_LEN..], version)
+    }
+
+    fn read_all(r: &mut FrameReader<&[u8]>) -> Result<Vec<Frame>> {
+        let mut frames = Vec::new();
+        while let Some(frame) = r.read_frame()? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = file();
+        let mut r = reader(&bytes);
+        let frames = read_all(&mut r).unwrap();
+        assert!(r.verified);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].kind, META);
+        assert_eq!(frames[0].offset, HEADER_LEN as u64);
+        assert_eq!(frames[0].decode::<u32>().unwrap(), 60);
+        assert_eq!(frames[1].kind, TICK);
+        assert_eq!(frames[1].decode::<String>().unwrap(), "tick three");
+    }
+
+    #[test]
+    fn truncated_record() {
+        let bytes = file();
+        // into the tick record: kind, length, then 2 of its payload bytes
+        let tick_at = HEADER_LEN + 5 + 1;
+        let cut = &bytes[..tick_at + 7];
+
+        let err = read_all(&mut reader(cut)).err().unwrap();
+        assert!(err.to_string().contains("cut short"), "{err}");
+
+        let mut r = reader(cut);
+        r.recover = true;
+        assert_eq!(read_all(&mut r).unwrap().len(), 1);
+        assert_eq!(r.cut_at, Some(tick_at as u64));
+        assert!(!r.verified);
+    }
+
+    #[test]
+    fn cut_before_the_checksum() {
+        let bytes = file();
+        // right after the tick record, whose payload is 11 bytes
+        let checksum_at = HEADER_LEN + 5 + 1 + 5 + 11;
+        let cut = &bytes[..checksum_at];
+
+        let err = read_all(&mut reader(cut)).err().unwrap();
+        assert!(err.to_string().contains("before the checksum"), "{err}");
+
+        let mut r = reader(cut);
+        r.recover = true;
+        assert_eq!(read_all(&mut r).unwrap().len(), 2);
+        assert_eq!(r.cut_at, Some(checksum_at as u64));
+        assert!(!r.verified);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut bytes = file();
+        // the last byte of the tick's payload
+        let at = bytes.len() - 5 - 32 - 1;
+        bytes[at] ^= 1;
+        let err = read_all(&mut reader(&bytes)).err().unwrap();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+    }
+
+    #[test]
+    fn record_after_the_checksum() {
+        let mut bytes = file();
+        bytes.extend_from_slice(&[TICK, 0, 0, 0, 0]);
+        let err = read_all(&mut reader(&bytes)).err().unwrap();
+        assert!(
+            err.to_string().contains("record after the checksum"),
+            "{err}"
+        );
+    }
+}
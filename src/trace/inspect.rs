@@ -0,0 +1,470 @@
+use super::{Checkpoint, Crash, Record, RunMeta, TickEvents, Trailer};
+use crate::entity_serde;
+use hecs::Entity;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// An entity to filter on: `5` matches any generation of id 5, `5v1` only
+/// that one.
+#[derive(Debug, Clone, Copy)]
+pub struct EntityRef {
+    pub id: u32,
+    pub generation: Option<u32>,
+}
+
+impl EntityRef {
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.contains('v') {
+            let e = entity_serde::parse(s)?;
+            return Some(Self {
+                id: e.id(),
+                generation: Some(generation(e)),
+            });
+        }
+        Some(Self {
+            id: s.parse().ok()?,
+            generation: None,
+        })
+    }
+
+    pub fn matches(&self, e: Entity) -> bool {
+        e.id() == self.id && self.generation.is_none_or(|g| g == generation(e))
+    }
+}
+
+// hecs keeps the generation in the high half of the bits
+fn generation(e: Entity) -> u32 {
+    (e.to_bits().get() >> 32) as u32
+}
+
+/// Which records, and which parts of tick events, to show. Ticks are
+/// inclusive. With an entity or command filter, only the matching parts of
+/// tick events are kept and checkpoints are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub entity: Option<EntityRef>,
+    /// A `CommandType::name`.
+    pub command: Option<String>,
+    /// Keep tick events with nothing in them; every frame writes one.
+    pub empty: bool,
+}
+
+impl Filter {
+    fn in_range(&self, tick: u64) -> bool {
+        self.from.is_none_or(|from| tick >= from) && self.to.is_none_or(|to| tick <= to)
+    }
+
+    /// The part of `record` that passes, if any. The end of the run always does.
+    pub fn apply(&self, record: Record) -> Option<Record> {
+        let narrowed = self.entity.is_some() || self.command.is_some();
+        match record {
+            Record::Tick(ev) if !self.in_range(ev.tick) => None,
+            Record::Tick(mut ev) => {
+                if narrowed {
+                    self.narrow(&mut ev);
+                }
+                let empty =
+                    ev.props.is_none() && ev.commands.is_empty() && ev.node_trace.is_empty();
+                if empty && (narrowed || !self.empty) {
+                    return None;
+                }
+                Some(Record::Tick(ev))
+            }
+            Record::Checkpoint(cp) if narrowed || !self.in_range(cp.tick) => None,
+            record => Some(record),
+        }
+    }
+
+    fn narrow(&self, ev: &mut TickEvents) {
+        let entity = |e: Entity| self.entity.is_none_or(|r| r.matches(e));
+        ev.commands.retain(|cmd| {
+            entity(cmd.entity())
+                && self
+                    .command
+                    .as_ref()
+                    .is_none_or(|c| cmd.kind().name() == c.as_str())
+        });
+        if self.command.is_some() {
+            ev.props = None;
+            ev.node_trace.clear();
+        } else {
+            if ev
+                .props
+                .as_ref()
+                .is_some_and(|pd| !pd.selected_entity.is_some_and(entity))
+            {
+                ev.props = None;
+            }
+            ev.node_trace.retain(|t| entity(t.entity));
+        }
+    }
+}
+
+pub fn meta_text(meta: &RunMeta, version: u32) -> String {
+    format!(
+        "meta sim_hz={} seed={:#018x} format_version={version}",
+        meta.sim_hz, meta.seed
+    )
+}
+
+/// One record as text, multi-line for tick events with commands or node traces.
+pub fn record_text(record: &Record) -> String {
+    match record {
+        Record::Tick(ev) => tick_text(ev),
+        Record::Checkpoint(cp) => {
+            format!("checkpoint {} world_hash={:#018x}", cp.tick, cp.world_hash)
+        }
+        Record::End(trailer) => format!(
+            "end end_tick={} world_hash={:#018x}",
+            trailer.end_tick, trailer.final_world_hash
+        ),
+        Record::Crash(crash) => format!(
+            "crash tick={}\n  {}",
+            crash.tick,
+            crash.message.replace('\n', "\n  ")
+        ),
+    }
+}
+
+fn tick_text(ev: &TickEvents) -> String {
+    let mut out = format!("tick {}", ev.tick);
+    if let Some(pd) = &ev.props {
+        if let Some(e) = pd.selected_entity {
+            let _ = write!(out, " select={e:?}");
+        }
+        if let Some(b) = pd.draw_map_grid {
+            let _ = write!(out, " draw_map_grid={b}");
+        }
+        if let Some(b) = pd.quit {
+            let _ = write!(out, " quit={b}");
+        }
+    }
+    for cmd in &ev.commands {
+        let _ = write!(out, "\n  {:?} {:?}", cmd.entity(), cmd.kind());
+    }
+    for trace in &ev.node_trace {
+        let _ = write!(
+            out,
+            "\n  {:?} nodes {}",
+            trace.entity,
+            trace.to_string().replace('\n', "\n  ")
+        );
+    }
+    out
+}
+
+// JSON Lines: one object per record, keyed by its kind.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonRecord<'a> {
+    Meta {
+        sim_hz: u32,
+        seed: u64,
+        format_version: u32,
+    },
+    Tick(&'a TickEvents),
+    Checkpoint(&'a Checkpoint),
+    End(&'a Trailer),
+    Crash(&'a Crash),
+}
+
+pub fn meta_json(meta: &RunMeta, version: u32) -> String {
+    to_json(&JsonRecord::Meta {
+        sim_hz: meta.sim_hz,
+        seed: meta.seed,
+        format_version: version,
+    })
+}
+
+pub fn record_json(record: &Record) -> String {
+    to_json(&match record {
+        Record::Tick(ev) => JsonRecord::Tick(ev),
+        Record::Checkpoint(cp) => JsonRecord::Checkpoint(cp),
+        Record::End(trailer) => JsonRecord::End(trailer),
+        Record::Crash(crash) => JsonRecord::Crash(crash),
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    // only maps with string keys and plain values, can't fail
+    serde_json::to_string(value).expect("trace records serialize to JSON")
+}
+
+/// Counts over the records of a trace.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub first_tick: Option<u64>,
+    pub last_tick: Option<u64>,
+    /// Tick event records; a frame with several ticks writes one. Counts
+    /// only those `add`ed, so empty ones are left out if the filter drops
+    /// them.
+    pub events: u64,
+    pub prop_changes: u64,
+    pub commands: BTreeMap<&'static str, u64>,
+    /// Commands by entity, as `Debug` prints it.
+    pub commands_by_entity: BTreeMap<String, u64>,
+    pub node_traces: u64,
+    pub checkpoints: u64,
+    pub end: Option<Trailer>,
+    pub crash: Option<Crash>,
+}
+
+impl Summary {
+    pub fn add(&mut self, record: &Record) {
+        let tick = match record {
+            Record::Tick(ev) => {
+                self.events += 1;
+                if ev.props.is_some() {
+                    self.prop_changes += 1;
+                }
+                for cmd in &ev.commands {
+                    *self.commands.entry(cmd.kind().name()).or_default() += 1;
+                    *self
+                        .commands_by_entity
+                        .entry(format!("{:?}", cmd.entity()))
+                        .or_default() += 1;
+                }
+                self.node_traces += ev.node_trace.len() as u64;
+                ev.tick
+            }
+            Record::Checkpoint(cp) => {
+                self.checkpoints += 1;
+                cp.tick
+            }
+            Record::End(trailer) => {
+                self.end = Some(trailer.clone());
+                return;
+            }
+            Record::Crash(crash) => {
+                self.crash = Some(crash.clone());
+                return;
+            }
+        };
+        self.first_tick = Some(self.first_tick.map_or(tick, |t| t.min(tick)));
+        self.last_tick = Some(self.last_tick.map_or(tick, |t| t.max(tick)));
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        match (self.first_tick, self.last_tick) {
+            (Some(first), Some(last)) => {
+                let _ = writeln!(out, "ticks {first}..={last}");
+            }
+            _ => out.push_str("no ticks\n"),
+        }
+        let _ = writeln!(
+            out,
+            "events {} prop_changes {} node_traces {} checkpoints {}",
+            self.events, self.prop_changes, self.node_traces, self.checkpoints
+        );
+        let total: u64 = self.commands.values().sum();
+        let _ = writeln!(out, "commands {total}");
+        for (kind, n) in &self.commands {
+            let _ = writeln!(out, "  {kind} {n}");
+        }
+        if !self.commands_by_entity.is_empty() {
+            out.push_str("commands by entity\n");
+            for (entity, n) in &self.commands_by_entity {
+                let _ = writeln!(out, "  {entity} {n}");
+            }
+        }
+        match (&self.end, &self.crash) {
+            (Some(trailer), _) => {
+                out.push_str(&record_text(&Record::End(trailer.clone())));
+            }
+            (None, Some(crash)) => {
+                out.push_str(&record_text(&Record::Crash(crash.clone())));
+            }
+            (None, None) => out.push_str("no end record (cut short?)"),
+        }
+        out.push('\n');
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        to_json(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity_commands::{CommandType, EntityCommand};
+    use crate::node_trace::TickTrace;
+    use crate::trace::PropsDelta;
+
+    fn entity(id: u32, generation: u32) -> Entity {
+        Entity::from_bits(u64::from(generation) << 32 | u64::from(id)).unwrap()
+    }
+
+    fn tick(tick: u64, commands: Vec<EntityCommand>) -> Record {
+        Record::Tick(TickEvents {
+            tick,
+            props: None,
+            commands,
+            node_trace: Vec::new(),
+        })
+    }
+
+    fn remove(e: Entity) -> EntityCommand {
+        EntityCommand::new(e, CommandType::RemoveFromMap)
+    }
+
+    fn move_to(e: Entity) -> EntityCommand {
+        EntityCommand::new(e, CommandType::MoveToPosition { x: 1.0, y: 2.0 })
+    }
+
+    fn tick_of(record: Option<Record>) -> TickEvents {
+        match record {
+            Some(Record::Tick(ev)) => ev,
+            _ => panic!("expected tick events"),
+        }
+    }
+
+    #[test]
+    fn parses_entity_refs() {
+        let any = EntityRef::parse("5").unwrap();
+        assert!(any.matches(entity(5, 1)) && any.matches(entity(5, 2)));
+        assert!(!any.matches(entity(6, 1)));
+        let one = EntityRef::parse("5v2").unwrap();
+        assert!(one.matches(entity(5, 2)) && !one.matches(entity(5, 1)));
+        for bad in ["", "v1", "5v", "x", "5v1v2"] {
+            assert!(EntityRef::parse(bad).is_none(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn filter_keeps_ticks_in_range() {
+        let filter = Filter {
+            from: Some(10),
+            to: Some(20),
+            ..Filter::default()
+        };
+        let e = entity(1, 1);
+        assert!(filter.apply(tick(9, vec![remove(e)])).is_none());
+        assert!(filter.apply(tick(10, vec![remove(e)])).is_some());
+        assert!(filter.apply(tick(20, vec![remove(e)])).is_some());
+        assert!(filter.apply(tick(21, vec![remove(e)])).is_none());
+        let cp = |tick| {
+            Record::Checkpoint(Checkpoint {
+                tick,
+                world_hash: 0,
+                breakdown: crate::world_hash::WorldHashBreakdown {
+                    total: 0,
+                    pos: 0,
+                    hun: 0,
+                    sta: 0,
+                    item: 0,
+                    shape: 0,
+                },
+            })
+        };
+        assert!(filter.apply(cp(15)).is_some());
+        assert!(filter.apply(cp(30)).is_none());
+        let end = Record::End(Trailer {
+            end_tick: 30,
+            final_world_hash: 0,
+        });
+        assert!(filter.apply(end).is_some());
+    }
+
+    #[test]
+    fn filter_drops_empty_ticks_unless_asked() {
+        let mut filter = Filter::default();
+        assert!(filter.apply(tick(1, Vec::new())).is_none());
+        filter.empty = true;
+        assert!(filter.apply(tick(1, Vec::new())).is_some());
+    }
+
+    #[test]
+    fn filter_narrows_to_an_entity() {
+        let (a, b) = (entity(1, 1), entity(2, 1));
+        let filter = Filter {
+            entity: EntityRef::parse("1"),
+            // narrowed ticks that end up empty are dropped regardless
+            empty: true,
+            ..Filter::default()
+        };
+        let ev = tick_of(filter.apply(Record::Tick(TickEvents {
+            tick: 1,
+            props: Some(PropsDelta {
+                selected_entity: Some(b),
+                draw_map_grid: None,
+                quit: None,
+            }),
+            commands: vec![remove(a), remove(b)],
+            node_trace: vec![
+                TickTrace {
+                    tick: 1,
+                    entity: a,
+                    behavior: "idle".into(),
+                    visits: Vec::new(),
+                },
+                TickTrace {
+                    tick: 1,
+                    entity: b,
+                    behavior: "idle".into(),
+                    visits: Vec::new(),
+                },
+            ],
+        })));
+        assert!(ev.props.is_none());
+        assert_eq!(ev.commands.len(), 1);
+        assert_eq!(ev.commands[0].entity(), a);
+        assert_eq!(ev.node_trace.len(), 1);
+        assert_eq!(ev.node_trace[0].entity, a);
+        assert!(filter.apply(tick(2, vec![remove(b)])).is_none());
+    }
+
+    #[test]
+    fn filter_narrows_to_a_command() {
+        let e = entity(1, 1);
+        let filter = Filter {
+            command: Some("RemoveFromMap".into()),
+            ..Filter::default()
+        };
+        let ev = tick_of(filter.apply(tick(1, vec![move_to(e), remove(e)])));
+        assert_eq!(ev.commands.len(), 1);
+        assert_eq!(ev.commands[0].kind().name(), "RemoveFromMap");
+        assert!(filter.apply(tick(2, vec![move_to(e)])).is_none());
+    }
+
+    #[test]
+    fn summary_counts_records() {
+        let (a, b) = (entity(1, 1), entity(2, 1));
+        let mut summary = Summary::default();
+        summary.add(&tick(4, vec![remove(a), move_to(a)]));
+        summary.add(&tick(2, Vec::new()));
+        summary.add(&tick(9, vec![move_to(b)]));
+        summary.add(&Record::End(Trailer {
+            end_tick: 10,
+            final_world_hash: 0xabc,
+        }));
+
+        assert_eq!((summary.first_tick, summary.last_tick), (Some(2), Some(9)));
+        assert_eq!(summary.events, 3);
+        assert_eq!(summary.commands["MoveToPosition"], 2);
+        assert_eq!(summary.commands["RemoveFromMap"], 1);
+        assert_eq!(summary.commands_by_entity[&format!("{a:?}")], 2);
+        assert_eq!(summary.commands_by_entity[&format!("{b:?}")], 1);
+        assert_eq!(summary.end.as_ref().unwrap().end_tick, 10);
+
+        let text = summary.to_text();
+        assert!(text.starts_with("ticks 2..=9\nevents 3 "), "{text}");
+        assert!(text.contains("commands 3\n"), "{text}");
+        assert!(text.contains("end end_tick=10"), "{text}");
+        let json: serde_json::Value = serde_json::from_str(&summary.to_json()).unwrap();
+        assert_eq!(json["events"], 3);
+        assert_eq!(json["commands"]["MoveToPosition"], 2);
+    }
+
+    #[test]
+    fn empty_summary() {
+        let text = Summary::default().to_text();
+        assert!(text.starts_with("no ticks\n"), "{text}");
+        assert!(text.contains("no end record"), "{text}");
+    }
+}
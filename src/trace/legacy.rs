@@ -0,0 +1,318 @@
+use super::{Record, RunMeta, TickEvents, Trailer};
+use crate::entity_commands::{CommandType, EntityCommand};
+use anyhow::{anyhow, bail, Result};
+use bincode::config::standard;
+use bincode::error::DecodeError;
+use bincode::serde::decode_from_slice;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+// Before the framed format, a trace was a bare bincode stream of `MetaV1`,
+// then `TickEventsV1` for every tick with events, then `TrailerV1`. These
+// types pin that layout; they must not change with the live ones.
+//
+// Nothing marks the trailer. It's told apart by its hash: any real one is
+// above `u32::MAX` and so takes bincode's 8 byte form, whose marker byte
+// can't start the `Option` that follows the tick of a `TickEventsV1`.
+
+/// Format version of a bare stream, as written in its `MetaV1`.
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct MetaV1 {
+    sim_hz: u32,
+    seed: u64,
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PropsDeltaV1 {
+    // entity bits
+    selected_entity: Option<u64>,
+    draw_map_grid: Option<bool>,
+    quit: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum CommandTypeV1 {
+    MoveToPosition { x: f32, y: f32 },
+    RemoveFromMap,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntityCommandV1 {
+    entity: u64,
+    kind: CommandTypeV1,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TickEventsV1 {
+    tick: u64,
+    props: Option<PropsDeltaV1>,
+    commands: Vec<EntityCommandV1>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrailerV1 {
+    end_tick: u64,
+    final_world_hash: u64,
+}
+
+/// A bare stream read whole and migrated to current records.
+pub struct Migrated {
+    pub meta: RunMeta,
+    pub records: Vec<Record>,
+    /// Where the file ended without a trailer, when recovering.
+    pub cut_at: Option<u64>,
+}
+
+/// Read a whole pre-framing trace and migrate it. Fails if `bytes` isn't
+/// one, has garbage in it, or ends before the trailer; unless `recover` is
+/// set, then a file cut short yields the records before the cut, without `End`.
+pub fn read(bytes: &[u8], recover: bool) -> Result<Migrated> {
+    let mut at = 0;
+    let meta: MetaV1 = match decode(bytes, &mut at) {
+        Ok(Some(meta)) => meta,
+        _ => bail!("not an anvil trace file"),
+    };
+    if meta.version != VERSION {
+        bail!("unknown trace format version {}", meta.version);
+    }
+    let mut migrated = Migrated {
+        meta: RunMeta {
+            sim_hz: meta.sim_hz,
+            seed: meta.seed,
+        },
+        records: Vec::new(),
+        cut_at: None,
+    };
+
+    loop {
+        let offset = at;
+        let trailer = decode::<TrailerV1>(bytes, &mut at);
+        if let Ok(Some(trailer)) = &trailer {
+            if trailer.final_world_hash > u64::from(u32::MAX) {
+                if at != bytes.len() {
+                    bail!("records after the trailer at byte {offset}");
+                }
+                migrated.records.push(Record::End(Trailer {
+                    end_tick: trailer.end_tick,
+                    final_world_hash: trailer.final_world_hash,
+                }));
+                return Ok(migrated);
+            }
+        }
+        at = offset;
+
+        match decode::<TickEventsV1>(bytes, &mut at) {
+            Ok(Some(ev)) => {
+                let ev = migrate_tick(ev)
+                    .map_err(|e| anyhow!("bad tick record at byte {offset}: {e}"))?;
+                migrated.records.push(Record::Tick(ev));
+            }
+            Ok(None) => {
+                if !recover {
+                    bail!("file ends before the trailer, it was cut short");
+                }
+                migrated.cut_at = Some(offset as u64);
+                return Ok(migrated);
+            }
+            Err(e) => {
+                // a trailer cut short doesn't decode as a tick either
+                let cut = is_cut(&e) || trailer.as_ref().is_err_and(is_cut);
+                if !cut {
+                    bail!("bad record at byte {offset}, the file is corrupt: {e}");
+                }
+                if !recover {
+                    bail!("file ends inside the record at byte {offset}, it was cut short");
+                }
+                migrated.cut_at = Some(offset as u64);
+                return Ok(migrated);
+            }
+        }
+    }
+}
+
+fn migrate_tick(ev: TickEventsV1) -> Result<TickEvents, String> {
+    let props = match ev.props {
+        Some(props) => Some(super::PropsDelta {
+            selected_entity: props.selected_entity.map(entity).transpose()?,
+            draw_map_grid: props.draw_map_grid,
+            quit: props.quit,
+        }),
+        None => None,
+    };
+    let commands = ev
+        .commands
+        .into_iter()
+        .map(|cmd| {
+            let kind = match cmd.kind {
+                CommandTypeV1::MoveToPosition { x, y } => CommandType::MoveToPosition { x, y },
+                CommandTypeV1::RemoveFromMap => CommandType::RemoveFromMap,
+            };
+            Ok(EntityCommand::new(entity(cmd.entity)?, kind))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(TickEvents {
+        tick: ev.tick,
+        props,
+        commands,
+        node_trace: Vec::new(),
+    })
+}
+
+fn entity(bits: u64) -> Result<hecs::Entity, String> {
+    hecs::Entity::from_bits(bits).ok_or_else(|| format!("invalid entity bits {bits:#x}"))
+}
+
+fn is_cut(e: &DecodeError) -> bool {
+    matches!(e, DecodeError::UnexpectedEnd { .. })
+}
+
+// Decode the value at `at` and move past it; `None` at the end of the file.
+fn decode<T: DeserializeOwned>(bytes: &[u8], at: &mut usize) -> Result<Option<T>, DecodeError> {
+    if *at == bytes.len() {
+        return Ok(None);
+    }
+    let (value, used) = decode_from_slice(&bytes[*at..], standard())?;
+    *at += used;
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::serde::encode_into_std_write;
+
+    const HASH: u64 = 0x9e37_79b9_7f4a_7c15;
+    // bytes of the `MetaV1` in `v1_file`
+    const META_LEN: usize = 3;
+
+    fn put<T: Serialize>(bytes: &mut Vec<u8>, value: &T) {
+        encode_into_std_write(value, bytes, standard()).unwrap();
+    }
+
+    fn v1_file() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        put(
+            &mut bytes,
+            &MetaV1 {
+                sim_hz: 60,
+                seed: 7,
+                version: VERSION,
+            },
+        );
+        put(
+            &mut bytes,
+            &TickEventsV1 {
+                tick: 3,
+                props: Some(PropsDeltaV1 {
+                    selected_entity: Some(5 | 1 << 32),
+                    draw_map_grid: None,
+                    quit: None,
+                }),
+                commands: vec![EntityCommandV1 {
+                    entity: 5 | 1 << 32,
+                    kind: CommandTypeV1::MoveToPosition { x: 1.0, y: 2.0 },
+                }],
+            },
+        );
+        put(
+            &mut bytes,
+            &TickEventsV1 {
+                tick: 9,
+                props: None,
+                commands: vec![EntityCommandV1 {
+                    entity: 6 | 1 << 32,
+                    kind: CommandTypeV1::RemoveFromMap,
+                }],
+            },
+        );
+        put(
+            &mut bytes,
+            &TrailerV1 {
+                end_tick: 12,
+                final_world_hash: HASH,
+            },
+        );
+        bytes
+    }
+
+    #[test]
+    fn migrates_a_v1_file() {
+        let migrated = read(&v1_file(), false).unwrap();
+        assert_eq!((migrated.meta.sim_hz, migrated.meta.seed), (60, 7));
+        assert_eq!(migrated.cut_at, None);
+        let [Record::Tick(first), Record::Tick(second), Record::End(trailer)] =
+            &migrated.records[..]
+        else {
+            panic!("expected two ticks and the end");
+        };
+        assert_eq!(first.tick, 3);
+        let selected = first.props.as_ref().unwrap().selected_entity.unwrap();
+        assert_eq!(selected.to_bits().get(), 5 | 1 << 32);
+        assert!(matches!(
+            first.commands[0].kind(),
+            CommandType::MoveToPosition { x: 1.0, y: 2.0 }
+        ));
+        assert_eq!(second.tick, 9);
+        assert!(matches!(
+            second.commands[0].kind(),
+            CommandType::RemoveFromMap
+        ));
+        assert_eq!(
+            *trailer,
+            Trailer {
+                end_tick: 12,
+                final_world_hash: HASH
+            }
+        );
+    }
+
+    #[test]
+    fn a_cut_file_fails_unless_recovering() {
+        let bytes = v1_file();
+        // every cut lands before the trailer is whole
+        for len in 1..bytes.len() {
+            let cut = &bytes[..len];
+            let Ok(migrated) = read(cut, true) else {
+                // too short for the meta
+                assert!(len < META_LEN, "cut at {len} failed to recover");
+                continue;
+            };
+            assert!(migrated.cut_at.is_some(), "cut at {len} read as whole");
+            assert!(
+                migrated
+                    .records
+                    .iter()
+                    .all(|r| matches!(r, Record::Tick(_))),
+                "cut at {len} read a trailer"
+            );
+            let err = read(cut, false).err().unwrap();
+            assert!(err.to_string().contains("cut short"), "{err}");
+        }
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        let mut bytes = v1_file();
+        // an option tag that's neither 0 nor 1, after the first tick number
+        bytes[META_LEN + 1] = 7;
+        let err = read(&bytes, true).err().unwrap();
+        assert!(err.to_string().contains("corrupt"), "{err}");
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = Vec::new();
+        put(
+            &mut bytes,
+            &MetaV1 {
+                sim_hz: 60,
+                seed: 7,
+                version: 4,
+            },
+        );
+        assert!(read(&bytes, false).is_err());
+    }
+}
@@ -0,0 +1,539 @@
+pub mod format;
+pub mod inspect;
+pub mod legacy;
+
+use crate::world_hash::WorldHashBreakdown;
+use anyhow::{bail, Context, Result};
+use format::{FrameReader, FrameWriter};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Ticks between flushes of a recording, so a process killed outright loses
+/// at most this many ticks of it.
+pub const FLUSH_TICKS: u64 = 60;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RunMeta {
+    pub sim_hz: u32,
+    pub seed: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PropsDelta {
+    #[serde(with = "crate::entity_serde::opt")]
+    pub selected_entity: Option<hecs::Entity>,
+    pub draw_map_grid: Option<bool>,
+    pub quit: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TickEvents {
+    pub tick: u64,
+    pub props: Option<PropsDelta>,
+    pub commands: Vec<crate::entity_commands::EntityCommand>,
+    /// Node visits of the selected entity in the ticks since the previous
+    /// event; only written with `--trace-nodes`.
+    pub node_trace: Vec<crate::node_trace::TickTrace>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Trailer {
+    pub end_tick: u64,
+    pub final_world_hash: u64,
+}
+
+impl Trailer {
+    /// Compare a replay's end against the recorded one; `Err` holds a report
+    /// of what diverged.
+    pub fn verify(&self, replayed: &Trailer) -> Result<(), String> {
+        if self == replayed {
+            return Ok(());
+        }
+        let mut report = String::from("REPLAY DIVERGED from recording:");
+        if self.end_tick != replayed.end_tick {
+            report += &format!(
+                "\n  end_tick:   recorded {}, replayed {}",
+                self.end_tick, replayed.end_tick
+            );
+        }
+        if self.final_world_hash != replayed.final_world_hash {
+            report += &format!(
+                "\n  world_hash: recorded {:#018x}, replayed {:#018x}",
+                self.final_world_hash, replayed.final_world_hash
+            );
+        }
+        Err(report)
+    }
+}
+
+/// World hash after `tick`, written every few ticks so a replay can stop at
+/// the first tick that differs instead of only noticing at the end.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Checkpoint {
+    pub tick: u64,
+    pub world_hash: u64,
+    pub breakdown: WorldHashBreakdown,
+}
+
+impl Checkpoint {
+    pub fn new(tick: u64, breakdown: WorldHashBreakdown) -> Self {
+        Self {
+            tick,
+            world_hash: breakdown.total,
+            breakdown,
+        }
+    }
+
+    /// Compare against the replay's hash at the same tick; `Err` holds a report
+    /// listing every component kind and whether it diverged.
+    pub fn verify(&self, replayed: &WorldHashBreakdown) -> Result<(), String> {
+        if self.world_hash == replayed.total {
+            return Ok(());
+        }
+        let recorded = &self.breakdown;
+        let rows = [
+            ("world_hash", recorded.total, replayed.total),
+            ("position", recorded.pos, replayed.pos),
+            ("hunger", recorded.hun, replayed.hun),
+            ("state", recorded.sta, replayed.sta),
+            ("item", recorded.item, replayed.item),
+            ("shape", recorded.shape, replayed.shape),
+        ];
+        let mut report = format!(
+            "REPLAY DIVERGED from recording at checkpoint tick {}:",
+            self.tick
+        );
+        for (name, rec, rep) in rows {
+            let verdict = if rec == rep { "same" } else { "DIFFERS" };
+            report +=
+                &format!("\n  {name:<10} recorded {rec:#018x}, replayed {rep:#018x}  {verdict}");
+        }
+        Err(report)
+    }
+}
+
+/// Ends a recording in place of the trailer when the process panicked.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Crash {
+    /// The tick that was running; its events are in the recording.
+    pub tick: u64,
+    pub message: String,
+}
+
+/// What follows the `RunMeta` of a trace, ending with `End` or `Crash`.
+pub enum Record {
+    Tick(TickEvents),
+    Checkpoint(Checkpoint),
+    End(Trailer),
+    Crash(Crash),
+}
+
+struct RecorderState {
+    w: FrameWriter<BufWriter<File>>,
+    // the tick running now, for a crash record
+    tick: u64,
+}
+
+/// Writes a trace in the current format, see `format`.
+/// The state is shared with the panic hook, see `finish_on_panic`; it's
+/// `None` once the recording is finished.
+pub struct Recorder {
+    state: Arc<Mutex<Option<RecorderState>>>,
+}
+impl Recorder {
+    pub fn new<P: AsRef<Path>>(path: P, meta: RunMeta) -> Result<Self> {
+        let mut w = FrameWriter::new(BufWriter::new(File::create(path.as_ref())?))?;
+        w.record(format::META, &meta)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(Some(RecorderState { w, tick: 0 }))),
+        })
+    }
+    pub fn push(&mut self, ev: &TickEvents) -> Result<()> {
+        self.with_state(|state| state.w.record(format::TICK, ev))
+    }
+    pub fn checkpoint(&mut self, cp: &Checkpoint) -> Result<()> {
+        self.with_state(|state| state.w.record(format::CHECKPOINT, cp))
+    }
+    /// Call once `tick` is simulated; flushes every `FLUSH_TICKS` ticks.
+    pub fn end_tick(&mut self, tick: u64) -> Result<()> {
+        self.with_state(|state| {
+            state.tick = tick + 1;
+            if state.tick.is_multiple_of(FLUSH_TICKS) {
+                state.w.flush()?;
+            }
+            Ok(())
+        })
+    }
+    pub fn finish(self, trailer: &Trailer) -> Result<()> {
+        let Some(mut state) = lock(&self.state).take() else {
+            bail!("recording already finished");
+        };
+        state.w.record(format::END, trailer)?;
+        state.w.finish()?;
+        Ok(())
+    }
+
+    /// Install a panic hook that ends the recording with a `Crash` record and
+    /// its checksum, so a run that panics keeps everything recorded up to the
+    /// panic. The previous hook still runs first.
+    pub fn finish_on_panic(&self) {
+        let state = Arc::clone(&self.state);
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+            // a panic while writing leaves the state locked, give up on it then
+            let Ok(mut guard) = state.try_lock() else {
+                return;
+            };
+            let Some(mut state) = guard.take() else {
+                return;
+            };
+            let crash = Crash {
+                tick: state.tick,
+                message: info.to_string(),
+            };
+            let written = state
+                .w
+                .record(format::CRASH, &crash)
+                .and_then(|_| state.w.finish());
+            match written {
+                Ok(_) => eprintln!("recording ended with a crash at tick {}", crash.tick),
+                Err(e) => eprintln!("can't end the recording after the crash: {e:#}"),
+            }
+        }));
+    }
+
+    fn with_state(&self, f: impl FnOnce(&mut RecorderState) -> Result<()>) -> Result<()> {
+        match lock(&self.state).as_mut() {
+            Some(state) => f(state),
+            None => bail!("recording already finished"),
+        }
+    }
+}
+
+// The state is only ever taken whole, so a poisoned lock still holds a usable one.
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Where a player's records come from: a current file read as it goes, or an
+// old one migrated up front.
+enum Source {
+    Framed(Box<FrameReader<BufReader<File>>>),
+    Migrated {
+        records: VecDeque<Record>,
+        cut_at: Option<u64>,
+    },
+}
+
+pub struct Player {
+    source: Source,
+    pub meta: RunMeta,
+    /// Format version of the file; older than `format::FORMAT_VERSION` means
+    /// it was migrated.
+    pub version: u32,
+    pub eof_reached: bool,
+    pub last_tick_seen: u64,
+    /// How the recorded run ended; set once reading gets past the last events.
+    /// Stays `None` for recordings cut short or ended by a crash.
+    pub trailer: Option<Trailer>,
+    /// The panic that ended the recorded run, read like `trailer`.
+    pub crash: Option<Crash>,
+    peek: Option<TickEvents>,
+    // read ahead of the replay, oldest first
+    checkpoints: VecDeque<Checkpoint>,
+}
+
+impl Player {
+    /// Open a trace of the current format or, through `legacy`, an older one.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open(path.as_ref(), false)
+    }
+
+    /// Like `new`, but a file cut short (say, by a killed process) plays up to
+    /// its last complete record instead of failing there.
+    pub fn recover<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open(path.as_ref(), true)
+    }
+
+    fn open(path: &Path, recover: bool) -> Result<Self> {
+        let (source, meta, version) =
+            open(path, recover).with_context(|| format!("can't read trace {:?}", path))?;
+        Ok(Self {
+            source,
+            meta,
+            version,
+            eof_reached: false,
+            last_tick_seen: 0,
+            trailer: None,
+            crash: None,
+            peek: None,
+            checkpoints: VecDeque::new(),
+        })
+    }
+
+    /// Non-blocking: return all events for `tick`, set eof_reached at EOF.
+    pub fn next_for_tick(&mut self, tick: u64) -> Result<Vec<TickEvents>> {
+        let mut out = Vec::new();
+
+        if let Some(ev) = self.peek.take() {
+            if ev.tick == tick {
+                self.last_tick_seen = self.last_tick_seen.max(ev.tick);
+                out.push(ev);
+            } else {
+                self.peek = Some(ev);
+                return Ok(out);
+            }
+        }
+
+        const MAX_READS_PER_CALL: usize = 64;
+        for _ in 0..MAX_READS_PER_CALL {
+            if self.eof_reached {
+                break;
+            }
+            match self.next_record()? {
+                Some(Record::Tick(ev)) if ev.tick == tick => {
+                    self.last_tick_seen = self.last_tick_seen.max(ev.tick);
+                    out.push(ev);
+                }
+                Some(Record::Tick(ev)) => {
+                    self.last_tick_seen = self.last_tick_seen.max(ev.tick);
+                    self.peek = Some(ev);
+                    break;
+                }
+                Some(Record::Checkpoint(cp)) => {
+                    self.last_tick_seen = self.last_tick_seen.max(cp.tick);
+                    self.checkpoints.push_back(cp);
+                }
+                Some(Record::End(trailer)) => {
+                    self.read_to_end()?;
+                    self.trailer = Some(trailer);
+                }
+                Some(Record::Crash(crash)) => {
+                    self.read_to_end()?;
+                    self.crash = Some(crash);
+                }
+                None => {
+                    self.eof_reached = true;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    // After the last record: reading on checks the checksum.
+    fn read_to_end(&mut self) -> Result<()> {
+        if self.next_record()?.is_some() {
+            bail!("records after the end of the run");
+        }
+        self.eof_reached = true;
+        Ok(())
+    }
+
+    /// Where a recovering player found the file cut short, once it got there.
+    pub fn cut_at(&self) -> Option<u64> {
+        match &self.source {
+            Source::Framed(r) => r.cut_at,
+            Source::Migrated { cut_at, .. } => *cut_at,
+        }
+    }
+
+    /// The next record in the file, `None` once there are no more.
+    pub fn next_record(&mut self) -> Result<Option<Record>> {
+        let frame = match &mut self.source {
+            Source::Migrated { records, .. } => return Ok(records.pop_front()),
+            Source::Framed(r) => match r.read_frame()? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            },
+        };
+        let record = match frame.kind {
+            format::TICK => Record::Tick(frame.decode()?),
+            format::CHECKPOINT => Record::Checkpoint(frame.decode()?),
+            format::END => Record::End(frame.decode()?),
+            format::CRASH => Record::Crash(frame.decode()?),
+            kind => bail!(
+                "unexpected {} record (kind {kind}) at byte {}",
+                format::kind_name(kind),
+                frame.offset
+            ),
+        };
+        Ok(Some(record))
+    }
+
+    /// The recorded checkpoint for `tick`, if there is one. Checkpoints are
+    /// written after the events of the frame they fall in, so by the time the
+    /// replay reaches a tick its checkpoint has been read.
+    pub fn checkpoint_at(&mut self, tick: u64) -> Option<Checkpoint> {
+        while self.checkpoints.front().is_some_and(|cp| cp.tick < tick) {
+            self.checkpoints.pop_front();
+        }
+        if self.checkpoints.front()?.tick == tick {
+            return self.checkpoints.pop_front();
+        }
+        None
+    }
+}
+
+fn open(path: &Path, recover: bool) -> Result<(Source, RunMeta, u32)> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    let mut header_len = 0;
+    while header_len < magic.len() {
+        let n = r.read(&mut magic[header_len..])?;
+        if n == 0 {
+            break;
+        }
+        header_len += n;
+    }
+
+    if header_len < magic.len() || &magic != format::MAGIC {
+        // no magic: the bare bincode stream from before
+        let mut bytes = magic[..header_len].to_vec();
+        r.read_to_end(&mut bytes)?;
+        let migrated = legacy::read(&bytes, recover)?;
+        let source = Source::Migrated {
+            records: migrated.records.into(),
+            cut_at: migrated.cut_at,
+        };
+        return Ok((source, migrated.meta, legacy::VERSION));
+    }
+
+    let mut version = [0u8; 4];
+    r.read_exact(&mut version)
+        .context("file ends inside the header")?;
+    let version = u32::from_le_bytes(version);
+    if version > format::FORMAT_VERSION {
+        bail!(
+            "trace format version {version} was written by a newer build, this one reads up to version {}",
+            format::FORMAT_VERSION
+        );
+    }
+    if version < format::FORMAT_VERSION {
+        bail!("unknown trace format version {version}");
+    }
+    let mut frames = FrameReader::after_header(r, version);
+    frames.recover = recover;
+    let meta = match frames.read_frame()? {
+        Some(frame) if frame.kind == format::META => frame.decode()?,
+        _ => bail!("trace has no run meta record"),
+    };
+    Ok((Source::Framed(Box::new(frames)), meta, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity_commands::{CommandType, EntityCommand};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("anvil-{}-{name}.trace", std::process::id()))
+    }
+
+    fn breakdown(total: u64) -> WorldHashBreakdown {
+        WorldHashBreakdown {
+            total,
+            pos: 1,
+            hun: 2,
+            sta: 3,
+            item: 4,
+            shape: 5,
+        }
+    }
+
+    // a run with events at ticks 2 and 4, a checkpoint at 4 and an end at 6
+    fn record(path: &Path) {
+        let mut rec = Recorder::new(
+            path,
+            RunMeta {
+                sim_hz: 60,
+                seed: 7,
+            },
+        )
+        .unwrap();
+        let entity = hecs::Entity::from_bits(5 | 1 << 32).unwrap();
+        for tick in [2, 4] {
+            rec.push(&TickEvents {
+                tick,
+                props: None,
+                commands: vec![EntityCommand::new(entity, CommandType::RemoveFromMap)],
+                node_trace: Vec::new(),
+            })
+            .unwrap();
+        }
+        rec.checkpoint(&Checkpoint::new(4, breakdown(0xabc)))
+            .unwrap();
+        rec.finish(&Trailer {
+            end_tick: 6,
+            final_world_hash: 0xdef,
+        })
+        .unwrap();
+    }
+
+    fn play(p: &mut Player) -> Vec<u64> {
+        let mut ticks = Vec::new();
+        for tick in 0..10 {
+            ticks.extend(p.next_for_tick(tick).unwrap().iter().map(|ev| ev.tick));
+        }
+        ticks
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round-trip");
+        record(&path);
+        let mut p = Player::new(&path).unwrap();
+        assert_eq!((p.meta.sim_hz, p.meta.seed), (60, 7));
+        assert_eq!(p.version, format::FORMAT_VERSION);
+        assert_eq!(play(&mut p), [2, 4]);
+        assert!(p.eof_reached);
+        assert_eq!(p.checkpoint_at(4).unwrap().breakdown, breakdown(0xabc));
+        assert_eq!(
+            p.trailer,
+            Some(Trailer {
+                end_tick: 6,
+                final_world_hash: 0xdef
+            })
+        );
+        assert_eq!(p.cut_at(), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_recording() {
+        let path = temp_path("truncated");
+        record(&path);
+        let bytes = std::fs::read(&path).unwrap();
+        // into the end record, past the checkpoint
+        std::fs::write(&path, &bytes[..bytes.len() - 5 - 32 - 2]).unwrap();
+
+        let mut p = Player::new(&path).unwrap();
+        let err = (0..10)
+            .find_map(|tick| p.next_for_tick(tick).err())
+            .unwrap();
+        assert!(format!("{err:#}").contains("cut short"), "{err:#}");
+
+        let mut p = Player::recover(&path).unwrap();
+        assert_eq!(play(&mut p), [2, 4]);
+        assert!(p.cut_at().is_some());
+        assert_eq!(p.trailer, None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_newer_version() {
+        let path = temp_path("newer");
+        record(&path);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[format::MAGIC.len()] += 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let err = Player::new(&path).err().unwrap();
+        assert!(format!("{err:#}").contains("newer build"), "{err:#}");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use anvil::entity_commands::CommandType;
+use anvil::trace::inspect::{self, EntityRef, Filter, Summary};
+use anvil::trace::Player;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+pub fn usage() -> &'static str {
+    "Usage:
+      anvil trace FILE [--json] [--summary] [--from TICK] [--to TICK] [--entity ID]
+                       [--command KIND] [--empty] [--recover]
+
+    Prints a --record file: its run meta, tick events (property changes,
+    entity commands, node traces), checkpoints and how the run ended.
+      --json       one JSON object per record (JSON Lines) instead of text
+      --summary    counts of events, commands by kind and by entity instead
+                   of the records themselves
+      --from, --to only ticks in this range, both inclusive
+      --entity ID  only events about this entity, `5` for any generation of
+                   id 5, `5v1` for that one
+      --command KIND
+                   only commands of this kind (MoveToPosition, RemoveFromMap)
+      --empty      also tick events with nothing in them, written every frame;
+                   --summary always counts them
+      --recover    read a file cut short up to its last complete record
+
+    Example:
+      anvil trace run.bin --entity 3 --from 600 --to 1200
+"
+}
+
+/// `anvil trace ...`; `args` are the arguments after `trace`.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut path: Option<PathBuf> = None;
+    let mut filter = Filter::default();
+    let mut json = false;
+    let mut summary = false;
+    let mut recover = false;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, val)) if flag.starts_with("--") => (flag, Some(val.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| it.next().cloned())
+                .ok_or(format!("{flag} requires a value"))
+        };
+        match flag {
+            "-h" | "--help" => return Err(usage().to_string()),
+            "--json" => json = true,
+            "--summary" => summary = true,
+            "--empty" => filter.empty = true,
+            "--recover" => recover = true,
+            "--from" => filter.from = Some(parse_tick(flag, &value()?)?),
+            "--to" => filter.to = Some(parse_tick(flag, &value()?)?),
+            "--entity" => {
+                let v = value()?;
+                filter.entity = Some(EntityRef::parse(&v).ok_or(format!(
+                    "Invalid --entity value {v:?}; expected e.g. 5 or 5v1"
+                ))?);
+            }
+            "--command" => {
+                let v = value()?;
+                if !CommandType::NAMES.contains(&v.as_str()) {
+                    return Err(format!(
+                        "Unknown --command kind {v:?}; known kinds: {}",
+                        CommandType::NAMES.join(", ")
+                    ));
+                }
+                filter.command = Some(v);
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option: {other}\n{usage}", usage = usage()));
+            }
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(usage().to_string()),
+        }
+    }
+    let Some(path) = path else {
+        return Err(usage().to_string());
+    };
+
+    let player = if recover {
+        Player::recover(&path)
+    } else {
+        Player::new(&path)
+    };
+    let mut player = player.map_err(|e| format!("{e:#}"))?;
+
+    let mut out = std::io::stdout().lock();
+    if json {
+        line(&mut out, &inspect::meta_json(&player.meta, player.version))?;
+    } else {
+        line(&mut out, &inspect::meta_text(&player.meta, player.version))?;
+    }
+    if summary {
+        // count every frame's events, with something in them or not
+        filter.empty = true;
+    }
+    let mut counts = Summary::default();
+    // records before a bad one are still printed, then the error
+    while let Some(record) = player.next_record().map_err(|e| format!("{e:#}"))? {
+        let Some(record) = filter.apply(record) else {
+            continue;
+        };
+        if summary {
+            counts.add(&record);
+        } else if json {
+            line(&mut out, &inspect::record_json(&record))?;
+        } else {
+            line(&mut out, &inspect::record_text(&record))?;
+        }
+    }
+    if summary {
+        if json {
+            line(&mut out, &counts.to_json())?;
+        } else {
+            line(&mut out, counts.to_text().trim_end())?;
+        }
+    }
+    if let Some(at) = player.cut_at() {
+        eprintln!("file cut short at byte {at}, read up to there");
+    }
+    Ok(())
+}
+
+// Output is often piped into `head` or `less`; stop quietly when they quit.
+fn line(out: &mut impl Write, text: &str) -> Result<(), String> {
+    match writeln!(out, "{text}") {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => Err(format!("write to stdout: {e}")),
+    }
+}
+
+fn parse_tick(flag: &str, v: &str) -> Result<u64, String> {
+    v.parse()
+        .map_err(|_| format!("Invalid {flag} value; expected u64"))
+}
@@ -0,0 +1,90 @@
+use super::types::GoalDef;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::{fs, path::Path};
+
+pub fn load_dir(dir: &Path) -> Result<Vec<GoalDef>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("read_dir {:?}", dir))? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("ron") {
+            paths.push(path);
+        }
+    }
+    // goal order breaks score ties, keep it stable
+    paths.sort();
+
+    let mut goals = Vec::new();
+    let mut defined_in: HashMap<String, PathBuf> = HashMap::new();
+    for path in paths {
+        let text =
+            fs::read_to_string(&path).with_context(|| format!("read_to_string {:?}", path))?;
+        let list: Vec<GoalDef> =
+            ron::from_str(&text).with_context(|| format!("RON parse {:?}", path))?;
+        for goal in list {
+            // `UtilityDb::goal` would only ever find the first
+            if let Some(first) = defined_in.insert(goal.id.clone(), path.clone()) {
+                bail!(
+                    "{}: goal {:?}: duplicate id, first defined in {}",
+                    path.display(),
+                    goal.id,
+                    first.display()
+                );
+            }
+            goals.push(goal);
+        }
+    }
+    Ok(goals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOAL: &str =
+        r#"(id: "eat", tree: "eat", priority: 20, min_score: 0.4, considerations: [])"#;
+
+    // a fresh directory with these files in it
+    fn dir(name: &str, files: &[(&str, String)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("anvil-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn loads_goals_in_file_order() {
+        let dir = dir(
+            "goals",
+            &[
+                ("b.ron", format!("[{}]", GOAL.replace("eat", "b"))),
+                ("a.ron", format!("[{}]", GOAL.replace("eat", "a"))),
+                ("notes.txt", String::from("not a goal")),
+            ],
+        );
+        let goals = load_dir(&dir).unwrap();
+        let ids: Vec<&str> = goals.iter().map(|g| g.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert!(!goals[0].once);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let dir = dir(
+            "duplicate-goals",
+            &[
+                ("a.ron", format!("[{GOAL}]")),
+                ("b.ron", format!("[{GOAL}]")),
+            ],
+        );
+        let err = load_dir(&dir).err().unwrap().to_string();
+        assert!(err.contains("b.ron: goal \"eat\": duplicate id"), "{err}");
+        assert!(err.contains("a.ron"), "{err}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
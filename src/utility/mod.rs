@@ -0,0 +1,235 @@
+pub mod loader;
+pub mod types;
+
+use crate::behavior_trees::BehaviorTreeDb;
+use crate::components::{Hunger, Item, Position};
+use crate::items::ItemDb;
+use crate::time::{time_of_day, FixedDt, Tick};
+use crate::Knowledge;
+use hecs::World as ComponentRegistry;
+use std::fmt;
+use std::path::Path;
+use types::{GoalDef, Input};
+
+/// Goals agents choose between by utility score, loaded from assets.
+pub struct UtilityDb {
+    pub goals: Vec<GoalDef>,
+}
+
+/// What one consideration saw and scored.
+#[derive(Debug, Clone)]
+pub struct ConsiderationScore {
+    pub input: String,
+    pub raw: f32,
+    pub score: f32,
+}
+
+/// A goal's total score (the product of its considerations) with the parts
+/// it was made of, kept for debugging.
+#[derive(Debug, Clone)]
+pub struct GoalScore {
+    pub goal: String,
+    pub score: f32,
+    pub considerations: Vec<ConsiderationScore>,
+}
+
+impl fmt::Display for GoalScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:.3} [", self.goal, self.score)?;
+        for (i, c) in self.considerations.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {:.2} -> {:.3}", c.input, c.raw, c.score)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// World facts the inputs are read from.
+pub struct ScoreContext<'a> {
+    pub registry: &'a ComponentRegistry,
+    pub items: &'a ItemDb,
+    pub tick: Tick,
+    pub fixed: FixedDt,
+}
+
+impl UtilityDb {
+    /// Load goals from `dir`; every goal has to name a tree in `trees`.
+    pub fn load_from_assets<P: AsRef<Path>>(
+        dir: P,
+        trees: &BehaviorTreeDb,
+    ) -> anyhow::Result<Self> {
+        let goals = loader::load_dir(dir.as_ref())?;
+        for goal in &goals {
+            if !trees.contains(&goal.tree) {
+                anyhow::bail!("goal {:?}: unknown behavior tree {:?}", goal.id, goal.tree);
+            }
+        }
+        Ok(Self { goals })
+    }
+
+    pub fn goal(&self, id: &str) -> Option<&GoalDef> {
+        self.goals.iter().find(|g| g.id == id)
+    }
+
+    /// Score every goal for the agent, best first; ties keep definition order.
+    pub fn rank(&self, knowledge: &Knowledge, ctx: &ScoreContext) -> Vec<GoalScore> {
+        let mut ranked: Vec<GoalScore> = self
+            .goals
+            .iter()
+            .map(|goal| score_goal(goal, knowledge, ctx))
+            .collect();
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+        ranked
+    }
+}
+
+fn score_goal(goal: &GoalDef, knowledge: &Knowledge, ctx: &ScoreContext) -> GoalScore {
+    let mut score = 1.;
+    let mut considerations = Vec::with_capacity(goal.considerations.len());
+    for c in &goal.considerations {
+        let raw = read_input(&c.input, c.range.1, knowledge, ctx);
+        let (lo, hi) = c.range;
+        let x = if hi > lo {
+            ((raw - lo) / (hi - lo)).clamp(0., 1.)
+        } else {
+            0.
+        };
+        let s = c.curve.eval(x);
+        score *= s;
+        considerations.push(ConsiderationScore {
+            input: format!("{:?}", c.input),
+            raw,
+            score: s,
+        });
+    }
+    GoalScore {
+        goal: goal.id.clone(),
+        score,
+        considerations,
+    }
+}
+
+fn read_input(input: &Input, range_max: f32, knowledge: &Knowledge, ctx: &ScoreContext) -> f32 {
+    let own = knowledge.own_id;
+    match input {
+        Input::Hunger => ctx
+            .registry
+            .get::<&Hunger>(own)
+            .map_or(0., |h| h.value as f32),
+        Input::ResourceDistance(id_or_tag) => {
+            let Ok(own_pos) = ctx.registry.get::<&Position>(own) else {
+                return range_max;
+            };
+            let mut nearest = range_max;
+            for (_, (item, pos)) in ctx.registry.query::<(&Item, &Position)>().iter() {
+                let def = ctx.items.get(item.def_id);
+                if def.id != *id_or_tag && !def.has_tag(id_or_tag) {
+                    continue;
+                }
+                let (dx, dy) = (pos.x - own_pos.x, pos.y - own_pos.y);
+                nearest = nearest.min((dx * dx + dy * dy).sqrt());
+            }
+            nearest
+        }
+        Input::RecipeCompleteness => {
+            let Some(recipe) = &knowledge.recipe else {
+                return 0.;
+            };
+            let needed: u32 = recipe.ingredients.iter().map(|i| i.qty).sum();
+            if needed == 0 {
+                return 1.;
+            }
+            let have: u32 = recipe
+                .ingredients
+                .iter()
+                .map(|i| {
+                    let held = knowledge.inventory.get(&i.id).map_or(0, |v| v.len() as u32);
+                    held.min(i.qty)
+                })
+                .sum();
+            have as f32 / needed as f32
+        }
+        Input::TimeOfDay => time_of_day(ctx.tick, ctx.fixed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::types::ItemIndex;
+    use crate::time::FixedDt;
+    use types::{Consideration, Curve};
+
+    // a goal scoring `score` whatever the agent's state
+    fn flat_goal(id: &str, score: f32, min_score: f32) -> GoalDef {
+        GoalDef {
+            id: id.to_string(),
+            tree: String::from("eat"),
+            priority: 10,
+            min_score,
+            once: false,
+            considerations: vec![Consideration {
+                input: Input::TimeOfDay,
+                range: (0., 1.),
+                curve: Curve::Linear {
+                    slope: 0.,
+                    intercept: score,
+                },
+            }],
+        }
+    }
+
+    fn rank(goals: Vec<GoalDef>) -> Vec<(String, f32)> {
+        let mut registry = ComponentRegistry::new();
+        let agent = registry.spawn(());
+        let items = ItemDb(ItemIndex::default());
+        let ctx = ScoreContext {
+            registry: &registry,
+            items: &items,
+            tick: Tick(0),
+            fixed: FixedDt::from_hz(60),
+        };
+        UtilityDb { goals }
+            .rank(&Knowledge::new(agent), &ctx)
+            .into_iter()
+            .map(|s| (s.goal, s.score))
+            .collect()
+    }
+
+    #[test]
+    fn ranks_best_first() {
+        let ranked = rank(vec![
+            flat_goal("low", 0.2, 0.),
+            flat_goal("high", 0.9, 0.),
+            flat_goal("mid", 0.5, 0.),
+        ]);
+        assert_eq!(
+            ranked,
+            [
+                (String::from("high"), 0.9),
+                (String::from("mid"), 0.5),
+                (String::from("low"), 0.2)
+            ]
+        );
+    }
+
+    #[test]
+    fn ties_keep_definition_order() {
+        let ranked = rank(vec![
+            flat_goal("first", 0.5, 0.),
+            flat_goal("best", 0.7, 0.),
+            flat_goal("second", 0.5, 0.),
+        ]);
+        let ids: Vec<&str> = ranked.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["best", "first", "second"]);
+    }
+
+    #[test]
+    fn score_is_the_product_of_considerations() {
+        let mut goal = flat_goal("both", 0.5, 0.);
+        goal.considerations.push(goal.considerations[0].clone());
+        assert_eq!(rank(vec![goal]), [(String::from("both"), 0.25)]);
+    }
+}
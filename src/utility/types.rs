@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+/// Maps a normalized input in [0, 1] to a score in [0, 1].
+/// Curves are plain arithmetic (no `exp`/`powf`), so scores come out
+/// bit-identical on every platform and keep runs deterministic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    Linear {
+        slope: f32,
+        intercept: f32,
+    },
+    /// `x^exponent`, e.g. 2 for quadratic.
+    Power {
+        exponent: u32,
+    },
+    /// 0 below `threshold`, 1 from it on.
+    Step {
+        threshold: f32,
+    },
+    /// S-curve rising from 0 at `edge0` to 1 at `edge1`; swap the edges to fall.
+    Smoothstep {
+        edge0: f32,
+        edge1: f32,
+    },
+}
+
+impl Curve {
+    pub fn eval(&self, x: f32) -> f32 {
+        let y = match self {
+            Curve::Linear { slope, intercept } => slope * x + intercept,
+            Curve::Power { exponent } => (0..*exponent).fold(1., |acc, _| acc * x),
+            Curve::Step { threshold } => {
+                if x >= *threshold {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Curve::Smoothstep { edge0, edge1 } => {
+                let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+                t * t * (3. - 2. * t)
+            }
+        };
+        y.clamp(0., 1.)
+    }
+}
+
+/// What a consideration looks at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    /// The agent's `Hunger` value.
+    Hunger,
+    /// Distance in tiles to the nearest item on the map with this id or tag;
+    /// the top of the range if there is none.
+    ResourceDistance(String),
+    /// Share of the current recipe's ingredients already in the inventory,
+    /// 0 without a recipe.
+    RecipeCompleteness,
+    /// Fraction of the day passed, see `time::time_of_day`.
+    TimeOfDay,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Consideration {
+    pub input: Input,
+    /// Raw input values mapped to 0 and 1 before the curve; values outside are clamped.
+    pub range: (f32, f32),
+    pub curve: Curve,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalDef {
+    pub id: String,
+    /// Behavior tree from `assets/behaviors` that pursues the goal.
+    pub tree: String,
+    /// Scheduling priority of the tree, see `ScheduledBehavior`.
+    pub priority: u8,
+    /// Goals scoring below this are not scheduled.
+    pub min_score: f32,
+    /// Schedule the goal at most once per agent.
+    #[serde(default)]
+    pub once: bool,
+    pub considerations: Vec<Consideration>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_is_clamped_to_0_and_1() {
+        let rising = Curve::Linear {
+            slope: 2.,
+            intercept: -0.5,
+        };
+        assert_eq!(rising.eval(0.), 0.);
+        assert_eq!(rising.eval(0.5), 0.5);
+        assert_eq!(rising.eval(1.), 1.);
+        let falling = Curve::Linear {
+            slope: -0.4,
+            intercept: 1.,
+        };
+        assert_eq!(falling.eval(0.), 1.);
+        assert_eq!(falling.eval(1.), 0.6);
+    }
+
+    #[test]
+    fn power() {
+        let square = Curve::Power { exponent: 2 };
+        assert_eq!(square.eval(0.), 0.);
+        assert_eq!(square.eval(0.5), 0.25);
+        assert_eq!(square.eval(1.), 1.);
+        // x^0 is flat
+        assert_eq!(Curve::Power { exponent: 0 }.eval(0.), 1.);
+    }
+
+    #[test]
+    fn step_includes_the_threshold() {
+        let step = Curve::Step { threshold: 0.5 };
+        assert_eq!(step.eval(0.), 0.);
+        assert_eq!(step.eval(0.49), 0.);
+        assert_eq!(step.eval(0.5), 1.);
+        assert_eq!(step.eval(1.), 1.);
+    }
+
+    #[test]
+    fn smoothstep_is_flat_outside_its_edges() {
+        let rising = Curve::Smoothstep {
+            edge0: 0.25,
+            edge1: 0.75,
+        };
+        assert_eq!(rising.eval(0.), 0.);
+        assert_eq!(rising.eval(0.25), 0.);
+        assert_eq!(rising.eval(0.5), 0.5);
+        assert_eq!(rising.eval(0.75), 1.);
+        assert_eq!(rising.eval(1.), 1.);
+        let falling = Curve::Smoothstep {
+            edge0: 0.75,
+            edge1: 0.25,
+        };
+        assert_eq!(falling.eval(0.), 1.);
+        assert_eq!(falling.eval(1.), 0.);
+    }
+}
@@ -1,10 +1,15 @@
-use crate::util;
+use anvil::util;
+use anyhow::{bail, Context, Result};
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
 use sdl2::render::BlendMode::Blend;
 use sdl2::render::WindowCanvas;
+use sdl2::ttf::Font;
 use sdl2::Sdl;
+use serde::Deserialize;
+use std::{fs, path::Path};
 
+#[derive(Deserialize)]
 pub enum PaletteEntry {
     Rect {
         w: i32,
@@ -18,21 +23,71 @@ fn init_palette_entries() -> Vec<PaletteEntry> {
         PaletteEntry::Rect {
             w: 1,
             h: 1,
-            color: (0, 0, 0, 0),
+            color: (100, 100, 100, 100),
         }, // 0 - default gray
     ]
 }
 
-struct ShapePalette {
+pub struct ShapePalette {
     entries: Vec<PaletteEntry>,
 }
 
 impl ShapePalette {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             entries: init_palette_entries(),
         }
     }
+
+    /// Load palette entries from a RON list, indexed by shape id.
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).with_context(|| format!("read_to_string {:?}", path))?;
+        let entries: Vec<PaletteEntry> =
+            ron::from_str(&text).with_context(|| format!("RON parse {:?}", path))?;
+        if entries.is_empty() {
+            bail!("palette {:?} has no entries", path);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Entry for `shape_id`, falling back to entry 0 for unknown ids.
+    fn get(&self, shape_id: u16) -> &PaletteEntry {
+        self.entries
+            .get(shape_id as usize)
+            .unwrap_or(&self.entries[0])
+    }
+}
+
+/// A line of overlay text and its color.
+pub type TextLine = (String, (u8, u8, u8, u8));
+
+// Fonts tried for text overlays, first one that loads wins.
+const OVERLAY_FONTS: &[&str] = &[
+    "assets/fonts/overlay.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+];
+const OVERLAY_FONT_SIZE: u16 = 14;
+
+fn load_overlay_font() -> Option<Font<'static, 'static>> {
+    // fonts borrow the ttf context, which lives as long as the program anyway
+    let ttf = match sdl2::ttf::init() {
+        Ok(ttf) => Box::leak(Box::new(ttf)),
+        Err(e) => {
+            eprintln!("can't init SDL_ttf, text overlays disabled: {e}");
+            return None;
+        }
+    };
+    let font = OVERLAY_FONTS
+        .iter()
+        .find_map(|path| ttf.load_font(path, OVERLAY_FONT_SIZE).ok());
+    if font.is_none() {
+        eprintln!("no overlay font found in {OVERLAY_FONTS:?}, text overlays disabled");
+    }
+    font
 }
 
 pub struct Window {
@@ -41,10 +96,11 @@ pub struct Window {
     camera_zoom: usize,
     camera_dirty: bool,
     shape_palette: ShapePalette,
+    font: Option<Font<'static, 'static>>,
 }
 
 impl Window {
-    pub fn new(sdl_context: &Sdl) -> Self {
+    pub fn new(sdl_context: &Sdl, shape_palette: ShapePalette) -> Self {
         let sdl_video = sdl_context.video().unwrap();
         let sdl_window = sdl_video
             .window("Anvil", 1200, 800)
@@ -66,7 +122,8 @@ impl Window {
             camera_pos: (0., 0.),
             camera_zoom: 50,
             camera_dirty: true,
-            shape_palette: ShapePalette::new(),
+            shape_palette,
+            font: load_overlay_font(),
         }
     }
 
@@ -88,8 +145,14 @@ impl Window {
     }
 
     pub fn draw_map_tile(&mut self, tile_pos: (u32, u32), shape_id: u16) {
-        // let shape = self.shape_palette.entries[shape_id];
-        // self.draw_rect(tile_pos.0 as f32, tile_pos.1 as f32, shape, 1., self.shape_palette.entries[shape_id])
+        let PaletteEntry::Rect { w, h, color } = *self.shape_palette.get(shape_id);
+        self.draw_rect(
+            tile_pos.0 as f32,
+            tile_pos.1 as f32,
+            w as f32,
+            h as f32,
+            color,
+        );
     }
 
     pub fn draw_rect(
@@ -139,6 +202,54 @@ impl Window {
             .expect("Error drawing point.");
     }
 
+    /// Draw lines of text on a dark panel, top-left corner at `pos` in screen
+    /// pixels. Draws nothing without an overlay font.
+    pub fn draw_text_panel(&mut self, pos: (i32, i32), lines: &[TextLine]) {
+        let Some(font) = &self.font else {
+            return;
+        };
+        let line_height = font.recommended_line_spacing();
+        let width = lines
+            .iter()
+            .filter_map(|(text, _)| font.size_of(text).ok())
+            .map(|(w, _)| w)
+            .max()
+            .unwrap_or(0);
+
+        self.sdl_canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        self.sdl_canvas
+            .fill_rect(Rect::new(
+                pos.0 - 4,
+                pos.1 - 4,
+                width + 8,
+                (line_height * lines.len() as i32 + 8) as u32,
+            ))
+            .expect("Error drawing rectangle with SDL Canvas.");
+
+        let texture_creator = self.sdl_canvas.texture_creator();
+        for (i, (text, color)) in lines.iter().enumerate() {
+            if text.is_empty() {
+                continue; // SDL_ttf can't render empty strings
+            }
+            let surface = font
+                .render(text)
+                .blended(Color::RGBA(color.0, color.1, color.2, color.3))
+                .expect("Error rendering text.");
+            let texture = texture_creator
+                .create_texture_from_surface(&surface)
+                .expect("Error creating text texture.");
+            let target = Rect::new(
+                pos.0,
+                pos.1 + line_height * i as i32,
+                surface.width(),
+                surface.height(),
+            );
+            self.sdl_canvas
+                .copy(&texture, None, target)
+                .expect("Error drawing text.");
+        }
+    }
+
     pub fn draw_selection_marker(&mut self, x_world: f32, y_world: f32) {
         self.sdl_canvas.set_draw_color(Color::RGBA(0, 200, 0, 255));
 
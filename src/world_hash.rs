@@ -1,7 +1,8 @@
 use blake3::Hasher;
 use hecs::World as ComponentRegistry;
+use serde::{Deserialize, Serialize};
 
-use crate::components::{Food, Hunger, Position, Shape, State, StateType, Stone, Wood};
+use crate::components::{Hunger, Item, Position, Shape, State, StateType};
 
 #[inline]
 fn f32_bits_canonical(x: f32) -> u32 {
@@ -47,20 +48,18 @@ fn finish64(mut hasher: Hasher) -> u64 {
     u64::from_le_bytes(out)
 }
 
-#[cfg(feature = "hash_debug")]
-#[derive(Debug, Clone, Copy)]
+/// Hash of each component kind alongside the total, to tell which kind
+/// diverged. `total` equals `world_hash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WorldHashBreakdown {
     pub total: u64,
     pub pos: u64,
     pub hun: u64,
     pub sta: u64,
-    pub food: u64,
-    pub wood: u64,
-    pub stone: u64,
+    pub item: u64,
     pub shape: u64,
 }
 
-#[cfg(feature = "hash_debug")]
 pub fn world_hash_breakdown(registry: &ComponentRegistry) -> WorldHashBreakdown {
     let mut total_hasher = Hasher::new();
 
@@ -136,62 +135,25 @@ pub fn world_hash_breakdown(registry: &ComponentRegistry) -> WorldHashBreakdown
         finish64(h)
     };
 
-    // FOOD
-    let food = {
-        let mut ids: Vec<u64> = registry
-            .query::<&Food>()
+    // ITEM (definition id per item entity)
+    let item = {
+        let mut rows: Vec<(u64, u32)> = registry
+            .query::<&Item>()
             .iter()
-            .map(|(e, _)| e.to_bits().get())
+            .map(|(e, it)| (e.to_bits().get(), it.def_id))
             .collect();
-        ids.sort_unstable();
-
-        let mut h = Hasher::new();
-        feed_tag(&mut h, b"FOOD");
-        feed_tag(&mut total_hasher, b"FOOD");
-        for id in ids {
-            let idb = u64_le(id);
-            h.update(&idb);
-            total_hasher.update(&idb);
-        }
-        finish64(h)
-    };
-
-    // WOOD
-    let wood = {
-        let mut ids: Vec<u64> = registry
-            .query::<&Wood>()
-            .iter()
-            .map(|(e, _)| e.to_bits().get())
-            .collect();
-        ids.sort_unstable();
-
-        let mut h = Hasher::new();
-        feed_tag(&mut h, b"WOOD");
-        feed_tag(&mut total_hasher, b"WOOD");
-        for id in ids {
-            let idb = u64_le(id);
-            h.update(&idb);
-            total_hasher.update(&idb);
-        }
-        finish64(h)
-    };
-
-    // STONE
-    let stone = {
-        let mut ids: Vec<u64> = registry
-            .query::<&Stone>()
-            .iter()
-            .map(|(e, _)| e.to_bits().get())
-            .collect();
-        ids.sort_unstable();
+        rows.sort_unstable_by_key(|r| r.0);
 
         let mut h = Hasher::new();
-        feed_tag(&mut h, b"STON");
-        feed_tag(&mut total_hasher, b"STON");
-        for id in ids {
+        feed_tag(&mut h, b"ITEM");
+        feed_tag(&mut total_hasher, b"ITEM");
+        for (id, def_id) in rows {
             let idb = u64_le(id);
+            let db = u32_le(def_id);
             h.update(&idb);
+            h.update(&db);
             total_hasher.update(&idb);
+            total_hasher.update(&db);
         }
         finish64(h)
     };
@@ -237,9 +199,7 @@ pub fn world_hash_breakdown(registry: &ComponentRegistry) -> WorldHashBreakdown
         pos,
         hun,
         sta,
-        food,
-        wood,
-        stone,
+        item,
         shape,
     }
 }
@@ -295,41 +255,18 @@ pub fn world_hash(registry: &ComponentRegistry) -> u64 {
         }
     }
 
-    // FOOD / WOOD / STONE
-    {
-        let mut ids: Vec<u64> = registry
-            .query::<&Food>()
-            .iter()
-            .map(|(e, _)| e.to_bits().get())
-            .collect();
-        ids.sort_unstable();
-        feed_tag(&mut hasher, b"FOOD");
-        for id in ids {
-            hasher.update(&u64_le(id));
-        }
-    }
+    // ITEM
     {
-        let mut ids: Vec<u64> = registry
-            .query::<&Wood>()
+        let mut rows: Vec<(u64, u32)> = registry
+            .query::<&Item>()
             .iter()
-            .map(|(e, _)| e.to_bits().get())
+            .map(|(e, it)| (e.to_bits().get(), it.def_id))
             .collect();
-        ids.sort_unstable();
-        feed_tag(&mut hasher, b"WOOD");
-        for id in ids {
-            hasher.update(&u64_le(id));
-        }
-    }
-    {
-        let mut ids: Vec<u64> = registry
-            .query::<&Stone>()
-            .iter()
-            .map(|(e, _)| e.to_bits().get())
-            .collect();
-        ids.sort_unstable();
-        feed_tag(&mut hasher, b"STON");
-        for id in ids {
+        rows.sort_unstable_by_key(|r| r.0);
+        feed_tag(&mut hasher, b"ITEM");
+        for (id, def_id) in rows {
             hasher.update(&u64_le(id));
+            hasher.update(&u32_le(def_id));
         }
     }
 